@@ -0,0 +1,127 @@
+use futures_lite::io::{AsyncReadExt, Cursor};
+use tide::http::headers::{HeaderValues, Headers, CONTENT_TYPE};
+use tide::{Body, Request, Response};
+
+const TRUNCATION_MARKER: &str = "[truncated]";
+
+/// How much of a request or response body is attached to captured events.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum MaxRequestBodySize {
+    /// Never attach the body.
+    #[default]
+    None,
+    /// Attach up to 1,000 bytes of the body.
+    Small,
+    /// Attach up to 10,000 bytes of the body.
+    Medium,
+    /// Always attach the whole body.
+    Always,
+}
+
+impl MaxRequestBodySize {
+    fn limit(self) -> Option<usize> {
+        match self {
+            Self::None => Some(0),
+            Self::Small => Some(1_000),
+            Self::Medium => Some(10_000),
+            Self::Always => None,
+        }
+    }
+}
+
+/// Read the start of the request body and put it back in front of the unread rest
+///
+/// Returns the body as it is attached to events, with a truncation marker if it exceeds the
+/// maximum size. The request is left untouched if the body is not attached, and the body is not
+/// attached if it cannot be read.
+pub(crate) async fn peek_request_body<State>(
+    request: &mut Request<State>,
+    max_size: MaxRequestBodySize,
+) -> Option<String> {
+    let limit = max_size.limit();
+    if limit == Some(0) {
+        return None;
+    }
+
+    // Setting the body overwrites the `Content-Type` header with the MIME type of the body
+    let content_type = request.header(CONTENT_TYPE).cloned();
+    let (body, data) = peek_body(request.take_body(), limit).await;
+    request.set_body(body);
+    restore_content_type(request.as_mut(), content_type);
+    data
+}
+
+/// Read the start of the response body and put it back in front of the unread rest, like
+/// `peek_request_body`
+pub(crate) async fn peek_response_body(
+    response: &mut Response,
+    max_size: MaxRequestBodySize,
+) -> Option<String> {
+    let limit = max_size.limit();
+    if limit == Some(0) {
+        return None;
+    }
+
+    let content_type = response.header(CONTENT_TYPE).cloned();
+    let (body, data) = peek_body(response.take_body(), limit).await;
+    response.set_body(body);
+    restore_content_type(response.as_mut(), content_type);
+    data
+}
+
+/// Put back the `Content-Type` header as it was before the body was set, including its absence
+fn restore_content_type(headers: &mut Headers, content_type: Option<HeaderValues>) {
+    match content_type {
+        Some(content_type) => headers.insert(CONTENT_TYPE, &content_type),
+        None => headers.remove(CONTENT_TYPE),
+    };
+}
+
+/// Read up to `limit` bytes of the body, and rebuild the body with them in front of the rest
+///
+/// If the body cannot be read, the data is `None` and the body is rebuilt from the bytes read so
+/// far, so that the reader of the body gets the error.
+async fn peek_body(body: Body, limit: Option<usize>) -> (Body, Option<String>) {
+    let mime = body.mime().clone();
+    let len = body.len();
+
+    let mut buf = Vec::new();
+    let mut reader = body.into_reader();
+    let read = match limit {
+        // Read one byte past the limit to tell whether the body is truncated
+        Some(limit) => {
+            (&mut reader)
+                .take(limit as u64 + 1)
+                .read_to_end(&mut buf)
+                .await
+        }
+        None => reader.read_to_end(&mut buf).await,
+    };
+
+    let data = match (read, limit) {
+        (Err(_), _) => None,
+        (Ok(_), Some(limit)) if buf.len() > limit => {
+            let mut data = String::from_utf8_lossy(&buf[..char_boundary(&buf, limit)]).into_owned();
+            data.push_str(TRUNCATION_MARKER);
+            Some(data)
+        }
+        (Ok(_), _) => Some(String::from_utf8_lossy(&buf).into_owned()),
+    };
+
+    let mut body = Body::from_reader(Cursor::new(buf).chain(reader), len);
+    body.set_mime(mime);
+
+    (body, data)
+}
+
+/// Back off from `index` to the start of the UTF-8 character it falls into, if any
+///
+/// Only continuation bytes are skipped, so that a body which is not UTF-8 is still cut at
+/// `index`.
+fn char_boundary(buf: &[u8], index: usize) -> usize {
+    let is_continuation = |i: usize| buf.get(i).is_some_and(|x| x & 0b1100_0000 == 0b1000_0000);
+    (index.saturating_sub(3)..=index)
+        .rev()
+        .find(|&i| !is_continuation(i))
+        .unwrap_or(index)
+}
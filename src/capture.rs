@@ -0,0 +1,328 @@
+use std::fmt;
+use std::ops::{Bound, RangeBounds};
+use std::sync::Arc;
+
+use sentry_core::protocol::{Context, Event, Map};
+use sentry_core::Level;
+use tide::{Next, Request, Response, StatusCode};
+
+use crate::body::{self, MaxRequestBodySize};
+use crate::ext::RequestHub;
+use crate::{error, panic, session};
+
+type StatusRange = (Bound<u16>, Bound<u16>);
+type ErrorFilter = dyn Fn(&tide::Error) -> bool + Send + Sync;
+
+/// Decides which errors of responses are captured, and at which level.
+///
+/// The default policy captures errors of 5xx responses at the error level.
+#[derive(Clone)]
+pub struct CapturePolicy {
+    ranges: Vec<(StatusRange, Level)>,
+    ignored_statuses: Vec<StatusCode>,
+    error_filters: Vec<Arc<ErrorFilter>>,
+}
+
+impl CapturePolicy {
+    /// Creates a policy which captures nothing.
+    pub fn none() -> Self {
+        Self {
+            ranges: Vec::new(),
+            ignored_statuses: Vec::new(),
+            error_filters: Vec::new(),
+        }
+    }
+
+    /// Creates a policy which captures errors of 5xx responses at the error level.
+    pub fn server_errors() -> Self {
+        Self::none().capture(500..600, Level::Error)
+    }
+
+    /// Captures errors of responses whose status is in the given range, at the given level.
+    ///
+    /// If ranges overlap, the one added first decides the level.
+    pub fn capture<R>(mut self, statuses: R, level: Level) -> Self
+    where
+        R: RangeBounds<u16>,
+    {
+        let range = (
+            statuses.start_bound().cloned(),
+            statuses.end_bound().cloned(),
+        );
+        self.ranges.push((range, level));
+        self
+    }
+
+    /// Never captures errors of responses with the given status.
+    pub fn ignore_status(mut self, status: StatusCode) -> Self {
+        self.ignored_statuses.push(status);
+        self
+    }
+
+    /// Never captures errors whose inner error is of type `E`.
+    pub fn ignore_error<E>(self) -> Self
+    where
+        E: fmt::Display + fmt::Debug + Send + Sync + 'static,
+    {
+        self.ignore_error_if(|error| error.downcast_ref::<E>().is_some())
+    }
+
+    /// Never captures errors for which the given predicate returns `true`.
+    pub fn ignore_error_if<F>(mut self, predicate: F) -> Self
+    where
+        F: Fn(&tide::Error) -> bool + Send + Sync + 'static,
+    {
+        self.error_filters.push(Arc::new(predicate));
+        self
+    }
+
+    /// Returns the level to capture the error of a response with, if it should be captured
+    pub(crate) fn level(&self, status: StatusCode, error: &tide::Error) -> Option<Level> {
+        let level = self.status_level(status)?;
+        if self.error_filters.iter().any(|filter| filter(error)) {
+            return None;
+        }
+        Some(level)
+    }
+
+    /// Returns the level to capture a response which carries no error with, if it should be
+    /// captured
+    pub(crate) fn status_level(&self, status: StatusCode) -> Option<Level> {
+        if self.ignored_statuses.contains(&status) {
+            return None;
+        }
+        let (_, level) = self
+            .ranges
+            .iter()
+            .find(|(range, _)| range.contains(&(status as u16)))?;
+        Some(*level)
+    }
+}
+
+impl Default for CapturePolicy {
+    fn default() -> Self {
+        Self::server_errors()
+    }
+}
+
+impl fmt::Debug for CapturePolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CapturePolicy")
+            .field("ranges", &self.ranges)
+            .field("ignored_statuses", &self.ignored_statuses)
+            .field("error_filters", &self.error_filters.len())
+            .finish()
+    }
+}
+
+/// Middleware which captures the errors and panics of responses on the hub of the request.
+///
+/// The hub is the one [`SentryScopeMiddleware`] stored in the request extensions, requests which
+/// did not pass through it are passed on untouched. Placing this middleware deeper in the stack
+/// lets it see the errors before outer middlewares render them. [`SentryMiddleware`] combines
+/// both.
+///
+/// [`SentryScopeMiddleware`]: crate::SentryScopeMiddleware
+/// [`SentryMiddleware`]: crate::SentryMiddleware
+#[derive(Debug, Clone)]
+pub struct SentryCaptureMiddleware {
+    emit_header: bool,
+    capture_panics: bool,
+    capture_server_errors: bool,
+    capture_policy: CapturePolicy,
+    capture_error_responses: bool,
+    max_response_body_size: MaxRequestBodySize,
+}
+
+impl SentryCaptureMiddleware {
+    pub fn new() -> Self {
+        Self {
+            emit_header: false,
+            capture_panics: false,
+            capture_server_errors: true,
+            capture_policy: CapturePolicy::server_errors(),
+            capture_error_responses: false,
+            max_response_body_size: MaxRequestBodySize::None,
+        }
+    }
+
+    /// If configured the sentry id is attached to a X-Sentry-Event header.
+    pub fn emit_header(mut self, val: bool) -> Self {
+        self.emit_header = val;
+        self
+    }
+
+    /// Enables or disables capturing panics of the endpoint.
+    ///
+    /// A panic is reported as an unhandled exception on the request scope and turned into a 500
+    /// response. The first request installs a panic hook to record the stacktrace, which calls
+    /// the previously installed hook. The default is to let panics unwind.
+    ///
+    /// If another panic hook already reports the panic on the request hub, e.g. the panic
+    /// integration of the `sentry` crate, its event is kept and no second event is captured.
+    pub fn capture_panics(mut self, val: bool) -> Self {
+        self.capture_panics = val;
+        self
+    }
+
+    /// Enables or disables error reporting.
+    ///
+    /// When enabled, errors are reported as decided by the
+    /// [`capture_policy`](Self::capture_policy), which is kept when error reporting is disabled
+    /// and enabled again. The default is to report the errors of all 5xx responses.
+    pub fn capture_server_errors(mut self, val: bool) -> Self {
+        self.capture_server_errors = val;
+        self
+    }
+
+    /// Reconfigures which errors are reported, and at which level.
+    pub fn capture_policy(mut self, policy: CapturePolicy) -> Self {
+        self.capture_policy = policy;
+        self
+    }
+
+    /// Enables or disables capturing a message event for 5xx responses which carry no error.
+    ///
+    /// This reports responses built by hand, e.g. a 502 forwarded by a proxy endpoint, which are
+    /// otherwise invisible. Whether a status is captured, and at which level, is decided by the
+    /// capture policy. The default is to only capture the errors of responses.
+    pub fn capture_error_responses(mut self, val: bool) -> Self {
+        self.capture_error_responses = val;
+        self
+    }
+
+    /// Configures how much of the response body is attached to the events of 5xx responses which
+    /// carry no error, see [`capture_error_responses`](Self::capture_error_responses).
+    ///
+    /// The body is buffered up to this size and sent to the client unchanged. The default is to
+    /// never attach the body.
+    pub fn max_response_body_size(mut self, val: MaxRequestBodySize) -> Self {
+        self.max_response_body_size = val;
+        self
+    }
+
+    /// Pass the request on to `next`, and capture the panic or the error of the response
+    pub(crate) async fn run<State>(
+        &self,
+        request: Request<State>,
+        next: Next<'_, State>,
+    ) -> tide::Result
+    where
+        State: Clone + Send + Sync + 'static,
+    {
+        let request_hub = match request.ext::<RequestHub>() {
+            Some(request_hub) => request_hub.clone(),
+            None => return Ok(next.run(request).await),
+        };
+        let hub = &request_hub.hub;
+
+        let future = next.run(request);
+        let mut response = if self.capture_panics {
+            panic::install_hook();
+            hub.configure_scope(|scope| scope.add_event_processor(panic::record_hook_event));
+            match panic::CatchPanic::new(future).await {
+                Ok(response) => response,
+                Err(panic) => {
+                    let event_id = match panic.event_id {
+                        Some(event_id) => event_id,
+                        None => hub.capture_event(panic.to_event()),
+                    };
+                    *request_hub.reported.lock().unwrap() = Some(event_id);
+
+                    Response::new(StatusCode::InternalServerError)
+                }
+            }
+        } else {
+            future.await
+        };
+        request_hub.record_response(&response);
+
+        let reported = *request_hub.reported.lock().unwrap();
+        let error_response_level = if self.capture_server_errors
+            && self.capture_error_responses
+            && response.error().is_none()
+            && response.status().is_server_error()
+        {
+            self.capture_policy.status_level(response.status())
+        } else {
+            None
+        };
+        let event_id = if reported.is_some() {
+            reported
+        } else if let Some((error, level)) = response
+            .error()
+            .filter(|_| self.capture_server_errors)
+            .and_then(|error| {
+                let level = self.capture_policy.level(response.status(), error)?;
+                Some((error, level))
+            })
+        {
+            if request_hub.session {
+                // The error is reported as unhandled, which would mark the session as crashed
+                session::end_session_errored(hub);
+            }
+            // The error stays attached to the response, for outer middlewares to render it
+            let client = hub.client();
+            let mut event = error::event_from_error(error, client.as_ref().map(|x| x.options()));
+            event.level = level;
+            Some(hub.capture_event(event))
+        } else if let Some(level) = error_response_level {
+            let body = body::peek_response_body(&mut response, self.max_response_body_size).await;
+            let event = error_response_event(response.status(), level, body);
+            Some(hub.capture_event(event))
+        } else {
+            None
+        };
+
+        if let Some(event_id) = event_id {
+            *request_hub.reported.lock().unwrap() = Some(event_id);
+            if self.emit_header {
+                response.insert_header("x-sentry-event", event_id.as_simple().to_string());
+            }
+        }
+
+        Ok(response)
+    }
+}
+
+impl Default for SentryCaptureMiddleware {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[async_trait::async_trait]
+impl<State> tide::Middleware<State> for SentryCaptureMiddleware
+where
+    State: Clone + Send + Sync + 'static,
+{
+    async fn handle(&self, request: Request<State>, next: Next<'_, State>) -> tide::Result {
+        self.run(request, next).await
+    }
+}
+
+/// Build a message event of a 5xx response which carries no error
+///
+/// The event is named after the transaction of the request by the scope.
+fn error_response_event(status: StatusCode, level: Level, body: Option<String>) -> Event<'static> {
+    let mut response = Map::new();
+    response.insert("status_code".into(), (status as u16).into());
+    if let Some(body) = body {
+        response.insert("data".into(), body.into());
+    }
+
+    let mut event = Event {
+        message: Some(format!("{} {}", status as u16, status.canonical_reason())),
+        level,
+        // Group the responses of each transaction separately
+        fingerprint: vec!["{{ default }}".into(), "{{ transaction }}".into()].into(),
+        ..Default::default()
+    };
+    event
+        .tags
+        .insert("http.status_code".into(), (status as u16).to_string());
+    event
+        .contexts
+        .insert("response".into(), Context::Other(response));
+    event
+}
@@ -0,0 +1,169 @@
+use sentry_core::protocol::{Event, Mechanism, Stacktrace};
+use sentry_core::ClientOptions;
+
+/// Crates of the standard library, the web framework and the SDK, whose frames are not in-app
+const FRAMEWORK_CRATES: &[&str] = &[
+    "std",
+    "core",
+    "alloc",
+    "sentry_tide",
+    "sentry_core",
+    "sentry_anyhow",
+    "sentry_backtrace",
+    "anyhow",
+    "tide",
+    "http_types",
+    "async_h1",
+    "async_std",
+    "async_global_executor",
+    "async_executor",
+    "async_task",
+    "async_io",
+    "futures_lite",
+    "futures_util",
+    "futures_core",
+];
+
+/// Build an unhandled exception event of the error of a response
+///
+/// Every source of the error is a separate exception. With the `backtrace` feature, the backtrace
+/// of the error is attached to the outermost exception.
+pub(crate) fn event_from_error(
+    error: &tide::Error,
+    options: Option<&ClientOptions>,
+) -> Event<'static> {
+    let mut event = sentry_anyhow::event_from_error(error.as_ref());
+
+    // Exceptions are ordered from the root cause to the outermost error
+    if let Some(exception) = event.exception.last_mut() {
+        let mut mechanism = Mechanism {
+            ty: "tide".into(),
+            handled: Some(false),
+            ..Default::default()
+        };
+        mechanism
+            .data
+            .insert("status_code".into(), (error.status() as u16).into());
+        exception.mechanism = Some(mechanism);
+
+        if let (Some(stacktrace), Some(options)) = (&mut exception.stacktrace, options) {
+            process_stacktrace(stacktrace, options);
+        }
+    }
+
+    event
+}
+
+/// Trim the stacktrace and mark its frames as in-app by crate name
+///
+/// Frames matching the client's `in_app_include` option are in-app, then those of the framework
+/// crates are not. The client's `in_app_exclude` option applies to the remaining frames.
+fn process_stacktrace(stacktrace: &mut Stacktrace, options: &ClientOptions) {
+    for frame in &mut stacktrace.frames {
+        let Some(function) = frame.function.as_deref() else {
+            continue;
+        };
+        let included = options
+            .in_app_include
+            .iter()
+            .any(|x| function.trim_start_matches('<').starts_with(x));
+        if !included && FRAMEWORK_CRATES.contains(&crate_name(function)) {
+            frame.in_app = Some(false);
+        }
+    }
+    sentry_backtrace::process_event_stacktrace(stacktrace, options);
+}
+
+/// Parse the crate a function belongs to from its name
+fn crate_name(function: &str) -> &str {
+    let function = function.trim_start_matches('<');
+    // Functions of generic trait impls are named after the trait, e.g.
+    // `<F as tide::endpoint::Endpoint<State>>::call`
+    let function = match function.split_once(" as ") {
+        Some((ty, trait_)) if !ty.contains("::") => trait_,
+        _ => function,
+    };
+    let name = function.split("::").next().unwrap_or_default();
+    // Trait names without a path are followed by the closing bracket, e.g. `<T as Trait>::method`
+    name.split(['<', '>']).next().unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::error::Error;
+    use std::fmt;
+
+    use super::*;
+
+    #[derive(Debug)]
+    struct ChainError {
+        message: &'static str,
+        source: Option<Box<ChainError>>,
+    }
+
+    impl fmt::Display for ChainError {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            f.write_str(self.message)
+        }
+    }
+
+    impl Error for ChainError {
+        fn source(&self) -> Option<&(dyn Error + 'static)> {
+            self.source.as_deref().map(|x| x as _)
+        }
+    }
+
+    #[test]
+    fn event_from_error_chain() {
+        let root = ChainError {
+            message: "connection refused",
+            source: None,
+        };
+        let middle = ChainError {
+            message: "query failed",
+            source: Some(Box::new(root)),
+        };
+        let outer = ChainError {
+            message: "cannot load user",
+            source: Some(Box::new(middle)),
+        };
+        let error = tide::Error::new(503, outer);
+
+        let event = event_from_error(&error, None);
+
+        let values: Vec<_> = event
+            .exception
+            .values
+            .iter()
+            .map(|x| x.value.as_deref().unwrap())
+            .collect();
+        assert_eq!(
+            values,
+            ["connection refused", "query failed", "cannot load user"]
+        );
+        let mechanism = event.exception.last().unwrap().mechanism.as_ref().unwrap();
+        assert_eq!(mechanism.ty, "tide");
+        assert_eq!(mechanism.handled, Some(false));
+        assert_eq!(mechanism.data["status_code"], 503);
+        assert!(event.exception.values[..2]
+            .iter()
+            .all(|x| x.mechanism.is_none()));
+    }
+
+    #[test]
+    fn crate_name_of_functions() {
+        assert_eq!(crate_name("app::handlers::get_user"), "app");
+        assert_eq!(crate_name("tide::server::Server<State>::respond"), "tide");
+        assert_eq!(crate_name("<app::Handler as tide::Endpoint>::call"), "app");
+        assert_eq!(
+            crate_name("<F as tide::endpoint::Endpoint<State>>::call"),
+            "tide"
+        );
+        assert_eq!(crate_name("<T as Trait>::method"), "Trait");
+        assert_eq!(crate_name("<T as Trait<U>>::method"), "Trait");
+        assert_eq!(
+            crate_name("<alloc::boxed::Box<F> as core::ops::FnOnce<A>>::call_once"),
+            "alloc"
+        );
+    }
+}
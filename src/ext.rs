@@ -0,0 +1,140 @@
+use std::error::Error;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+use sentry_core::protocol::User;
+use sentry_core::types::Uuid;
+use sentry_core::{Breadcrumb, Hub, IntoBreadcrumbs, Scope};
+use tide::{Request, Response, StatusCode};
+
+/// The per-request hub, stored in the request extensions by [`SentryScopeMiddleware`]
+///
+/// [`SentryScopeMiddleware`]: crate::SentryScopeMiddleware
+#[derive(Debug, Clone)]
+pub(crate) struct RequestHub {
+    pub(crate) hub: Arc<Hub>,
+    /// The ID of the event the error of the request was already reported with
+    pub(crate) reported: Arc<Mutex<Option<Uuid>>>,
+    /// Whether the request is tracked as a session
+    pub(crate) session: bool,
+    /// The breadcrumb of the start of the request
+    breadcrumb: Breadcrumb,
+    started: Instant,
+    /// The breadcrumb of the response, once recorded
+    response_breadcrumb: Arc<Mutex<Option<Breadcrumb>>>,
+}
+
+impl RequestHub {
+    pub(crate) fn new(hub: Arc<Hub>, breadcrumb: Breadcrumb, session: bool) -> Self {
+        Self {
+            hub,
+            reported: Arc::new(Mutex::new(None)),
+            session,
+            breadcrumb,
+            started: Instant::now(),
+            response_breadcrumb: Arc::new(Mutex::new(None)),
+        }
+    }
+
+    /// Record the user and the breadcrumb of the response on the scope, before events of the
+    /// response are captured
+    ///
+    /// The breadcrumb is only recorded once, by the innermost middleware. Returns the breadcrumb.
+    pub(crate) fn record_response(&self, response: &Response) -> Breadcrumb {
+        if let Some(user) = response.ext::<User>() {
+            self.hub
+                .configure_scope(|scope| scope.set_user(Some(user.clone())));
+        }
+        self.record_status(response.status())
+    }
+
+    /// Record the breadcrumb of the response with the given status on the scope, like
+    /// `record_response`, e.g. if a middleware returned an error instead of a response
+    pub(crate) fn record_status(&self, status: StatusCode) -> Breadcrumb {
+        let mut response_breadcrumb = self.response_breadcrumb.lock().unwrap();
+        response_breadcrumb
+            .get_or_insert_with(|| {
+                let breadcrumb = crate::http_response_breadcrumb(
+                    self.breadcrumb.clone(),
+                    status,
+                    self.started.elapsed(),
+                );
+                self.hub.add_breadcrumb(breadcrumb.clone());
+                breadcrumb
+            })
+            .clone()
+    }
+}
+
+/// Extension methods to reach the Sentry hub of a request from handlers.
+pub trait SentryRequestExt {
+    /// Returns the hub of the request.
+    ///
+    /// Falls back to the current hub if the request did not pass through [`SentryMiddleware`] or
+    /// [`SentryScopeMiddleware`].
+    ///
+    /// [`SentryMiddleware`]: crate::SentryMiddleware
+    /// [`SentryScopeMiddleware`]: crate::SentryScopeMiddleware
+    fn sentry_hub(&self) -> Arc<Hub>;
+
+    /// Invokes a function that can modify the scope of the request.
+    fn configure_sentry_scope<F>(&self, f: F)
+    where
+        F: FnOnce(&mut Scope);
+
+    /// Records breadcrumbs on the scope of the request.
+    fn add_sentry_breadcrumb<B>(&self, breadcrumb: B)
+    where
+        B: IntoBreadcrumbs;
+
+    /// Captures an error on the hub of the request, and marks the request as reported with it.
+    fn capture_sentry_error<E>(&self, error: &E) -> Uuid
+    where
+        E: Error + ?Sized;
+
+    /// Marks the error of the request as already reported with the given event.
+    ///
+    /// [`SentryMiddleware`] does not capture the error of the response then, but still emits the
+    /// given event ID in the `x-sentry-event` header if configured to.
+    ///
+    /// [`SentryMiddleware`]: crate::SentryMiddleware
+    fn mark_sentry_reported(&self, event_id: Uuid);
+}
+
+impl<State> SentryRequestExt for Request<State> {
+    fn sentry_hub(&self) -> Arc<Hub> {
+        match self.ext::<RequestHub>() {
+            Some(RequestHub { hub, .. }) => hub.clone(),
+            None => Hub::current(),
+        }
+    }
+
+    fn configure_sentry_scope<F>(&self, f: F)
+    where
+        F: FnOnce(&mut Scope),
+    {
+        self.sentry_hub().configure_scope(f);
+    }
+
+    fn add_sentry_breadcrumb<B>(&self, breadcrumb: B)
+    where
+        B: IntoBreadcrumbs,
+    {
+        self.sentry_hub().add_breadcrumb(breadcrumb);
+    }
+
+    fn capture_sentry_error<E>(&self, error: &E) -> Uuid
+    where
+        E: Error + ?Sized,
+    {
+        let event_id = self.sentry_hub().capture_error(error);
+        self.mark_sentry_reported(event_id);
+        event_id
+    }
+
+    fn mark_sentry_reported(&self, event_id: Uuid) {
+        if let Some(RequestHub { reported, .. }) = self.ext::<RequestHub>() {
+            *reported.lock().unwrap() = Some(event_id);
+        }
+    }
+}
@@ -0,0 +1,119 @@
+use regex::Regex;
+
+/// Matches request paths, to ignore requests with [`SentryMiddleware::ignore_paths`].
+///
+/// [`SentryMiddleware::ignore_paths`]: crate::SentryMiddleware::ignore_paths
+#[derive(Debug, Clone)]
+pub struct PathMatcher(Matcher);
+
+#[derive(Debug, Clone)]
+enum Matcher {
+    Exact(String),
+    Prefix(String),
+    Regex(Regex),
+}
+
+impl PathMatcher {
+    /// Matches exactly the given path.
+    pub fn exact(path: impl Into<String>) -> Self {
+        Self(Matcher::Exact(path.into()))
+    }
+
+    /// Matches the paths starting with the given prefix.
+    pub fn prefix(prefix: impl Into<String>) -> Self {
+        Self(Matcher::Prefix(prefix.into()))
+    }
+
+    /// Matches the paths matching the given glob pattern.
+    ///
+    /// `*` matches within a path segment, `**` matches across segments and `?` matches a single
+    /// character other than `/`.
+    pub fn glob(pattern: &str) -> Self {
+        let mut regex = String::from("^");
+        let mut chars = pattern.chars().peekable();
+        while let Some(c) = chars.next() {
+            match c {
+                '*' if chars.peek() == Some(&'*') => {
+                    chars.next();
+                    regex.push_str(".*");
+                }
+                '*' => regex.push_str("[^/]*"),
+                '?' => regex.push_str("[^/]"),
+                c => regex.push_str(&regex::escape(c.encode_utf8(&mut [0; 4]))),
+            }
+        }
+        regex.push('$');
+
+        Self(Matcher::Regex(
+            Regex::new(&regex).expect("escaped glob pattern is a valid regex"),
+        ))
+    }
+
+    /// Matches the paths matching the given regular expression.
+    ///
+    /// The expression is not anchored, use `^` and `$` to match whole paths.
+    pub fn regex(pattern: &str) -> Result<Self, regex::Error> {
+        Ok(Self(Matcher::Regex(Regex::new(pattern)?)))
+    }
+
+    pub(crate) fn is_match(&self, path: &str) -> bool {
+        match &self.0 {
+            Matcher::Exact(x) => path == x,
+            Matcher::Prefix(x) => path.starts_with(x.as_str()),
+            Matcher::Regex(x) => x.is_match(path),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn exact_and_prefix() {
+        let exact = PathMatcher::exact("/health");
+        assert!(exact.is_match("/health"));
+        assert!(!exact.is_match("/health/db"));
+        assert!(!exact.is_match("/healthz"));
+
+        let prefix = PathMatcher::prefix("/static/");
+        assert!(prefix.is_match("/static/app.js"));
+        assert!(!prefix.is_match("/static"));
+        assert!(!prefix.is_match("/api/static/app.js"));
+    }
+
+    #[test]
+    fn glob() {
+        let star = PathMatcher::glob("/assets/*.css");
+        assert!(star.is_match("/assets/app.css"));
+        assert!(star.is_match("/assets/.css"));
+        assert!(!star.is_match("/assets/css/app.css"));
+        assert!(!star.is_match("/assets/app.css.map"));
+
+        let double_star = PathMatcher::glob("/assets/**.css");
+        assert!(double_star.is_match("/assets/css/app.css"));
+
+        let question_mark = PathMatcher::glob("/v?/health");
+        assert!(question_mark.is_match("/v1/health"));
+        assert!(!question_mark.is_match("/v10/health"));
+        assert!(!question_mark.is_match("/v//health"));
+
+        // Regex metacharacters are matched literally
+        let literal = PathMatcher::glob("/a.b+(c)");
+        assert!(literal.is_match("/a.b+(c)"));
+        assert!(!literal.is_match("/axbb(c)"));
+    }
+
+    #[test]
+    fn regex() {
+        let unanchored = PathMatcher::regex(r"/\d+/").unwrap();
+        assert!(unanchored.is_match("/users/42/posts"));
+        assert!(!unanchored.is_match("/users/me/posts"));
+
+        let anchored = PathMatcher::regex(r"^/users/\d+$").unwrap();
+        assert!(anchored.is_match("/users/42"));
+        assert!(!anchored.is_match("/users/42/posts"));
+
+        assert!(PathMatcher::regex("(").is_err());
+    }
+}
@@ -1,124 +1,301 @@
 use std::borrow::Cow;
+use std::fmt;
 use std::sync::Arc;
+use std::time::{Duration, SystemTime};
 
-use sentry_anyhow::AnyhowHubExt;
-use sentry_core::protocol::{ClientSdkPackage, Event, Request as SentryRequest};
-use sentry_core::{Hub, SentryFutureExt};
-use tide::Request;
+use sentry_core::protocol::{
+    ClientSdkPackage, Event, IpAddress, Request as SentryRequest, SpanStatus, User, Value,
+};
+use sentry_core::{Breadcrumb, Hub, Level, Scope, TransactionContext};
+use tide::http::headers::HeaderName;
+use tide::http::Method;
+use tide::{Request, StatusCode};
 
-#[derive(Debug)]
-pub struct SentryMiddleware {
-    hub: Option<Arc<Hub>>,
-    emit_header: bool,
-    capture_server_errors: bool,
+mod body;
+mod capture;
+mod error;
+mod ext;
+mod ignore;
+#[cfg(feature = "listen")]
+mod listen;
+mod panic;
+mod propagation;
+mod proxy;
+mod route;
+mod scope;
+mod scrub;
+mod session;
+#[cfg(feature = "surf")]
+pub mod surf;
+#[cfg(feature = "async-std-transport")]
+pub mod transport;
+
+pub use body::MaxRequestBodySize;
+pub use capture::{CapturePolicy, SentryCaptureMiddleware};
+pub use ext::SentryRequestExt;
+pub use ignore::PathMatcher;
+#[cfg(feature = "listen")]
+pub use listen::{listen_with_flush, InFlightRequests};
+pub use proxy::{ForwardedHeader, TrustedProxies};
+pub use route::{route, MatchedRoute, SentryRouteMiddleware};
+pub use scope::SentryScopeMiddleware;
+
+/// Middleware which reports the requests of a tide server to Sentry.
+///
+/// It combines [`SentryScopeMiddleware`] and [`SentryCaptureMiddleware`], which can be used
+/// separately to let other middlewares sit between scope setup and error capture.
+pub struct SentryMiddleware<State> {
+    scope: SentryScopeMiddleware<State>,
+    capture: SentryCaptureMiddleware,
 }
 
-impl SentryMiddleware {
+impl<State> SentryMiddleware<State> {
     pub fn new() -> Self {
         Self {
-            hub: None,
-            emit_header: false,
-            capture_server_errors: true,
+            scope: SentryScopeMiddleware::new(),
+            capture: SentryCaptureMiddleware::new(),
         }
     }
 
-    /// Reconfigures the middleware so that it uses a specific hub instead of the default one.
+    /// Uses a specific hub, see [`SentryScopeMiddleware::with_hub`].
     pub fn with_hub(mut self, hub: Arc<Hub>) -> Self {
-        self.hub = Some(hub);
+        self.scope = self.scope.with_hub(hub);
         self
     }
 
-    /// Reconfigures the middleware so that it uses a specific hub instead of the default one.
+    /// Uses the main hub, see [`SentryScopeMiddleware::with_default_hub`].
     pub fn with_default_hub(mut self) -> Self {
-        self.hub = None;
+        self.scope = self.scope.with_default_hub();
+        self
+    }
+
+    /// Picks the hub of each request, see [`SentryScopeMiddleware::with_hub_selector`].
+    pub fn with_hub_selector<F>(mut self, selector: F) -> Self
+    where
+        F: Fn(&Request<State>) -> Arc<Hub> + Send + Sync + 'static,
+    {
+        self.scope = self.scope.with_hub_selector(selector);
         self
     }
 
-    /// If configured the sentry id is attached to a X-Sentry-Event header.
+    /// Attaches the event ID to responses, see [`SentryCaptureMiddleware::emit_header`].
     pub fn emit_header(mut self, val: bool) -> Self {
-        self.emit_header = val;
+        self.capture = self.capture.emit_header(val);
         self
     }
 
-    /// Enables or disables error reporting.
-    ///
-    /// The default is to report all errors.
+    /// Captures panics, see [`SentryCaptureMiddleware::capture_panics`].
+    pub fn capture_panics(mut self, val: bool) -> Self {
+        self.capture = self.capture.capture_panics(val);
+        self
+    }
+
+    /// Toggles error reporting, see [`SentryCaptureMiddleware::capture_server_errors`].
     pub fn capture_server_errors(mut self, val: bool) -> Self {
-        self.capture_server_errors = val;
+        self.capture = self.capture.capture_server_errors(val);
+        self
+    }
+
+    /// Records 4xx breadcrumbs, see [`SentryScopeMiddleware::client_error_breadcrumbs`].
+    pub fn client_error_breadcrumbs(mut self, val: bool) -> Self {
+        self.scope = self.scope.client_error_breadcrumbs(val);
+        self
+    }
+
+    /// Decides which errors are reported, see [`SentryCaptureMiddleware::capture_policy`].
+    pub fn capture_policy(mut self, policy: CapturePolicy) -> Self {
+        self.capture = self.capture.capture_policy(policy);
+        self
+    }
+
+    /// Captures error responses, see [`SentryCaptureMiddleware::capture_error_responses`].
+    pub fn capture_error_responses(mut self, val: bool) -> Self {
+        self.capture = self.capture.capture_error_responses(val);
+        self
+    }
+
+    /// Attaches the response body, see [`SentryCaptureMiddleware::max_response_body_size`].
+    pub fn max_response_body_size(mut self, val: MaxRequestBodySize) -> Self {
+        self.capture = self.capture.max_response_body_size(val);
+        self
+    }
+
+    /// Starts transactions, see [`SentryScopeMiddleware::start_transaction`].
+    pub fn start_transaction(mut self, val: bool) -> Self {
+        self.scope = self.scope.start_transaction(val);
+        self
+    }
+
+    /// Tracks sessions, see [`SentryScopeMiddleware::start_session`].
+    pub fn start_session(mut self, val: bool) -> Self {
+        self.scope = self.scope.start_session(val);
+        self
+    }
+
+    /// Samples transactions, see [`SentryScopeMiddleware::with_traces_sampler`].
+    pub fn with_traces_sampler<F>(mut self, sampler: F) -> Self
+    where
+        F: Fn(&TransactionContext) -> f32 + Send + Sync + 'static,
+    {
+        self.scope = self.scope.with_traces_sampler(sampler);
+        self
+    }
+
+    /// Attaches the request body, see [`SentryScopeMiddleware::max_request_body_size`].
+    pub fn max_request_body_size(mut self, val: MaxRequestBodySize) -> Self {
+        self.scope = self.scope.max_request_body_size(val);
+        self
+    }
+
+    /// Always attaches a header, see [`SentryScopeMiddleware::allow_header`].
+    pub fn allow_header(mut self, name: impl Into<HeaderName>) -> Self {
+        self.scope = self.scope.allow_header(name);
+        self
+    }
+
+    /// Never attaches a header, see [`SentryScopeMiddleware::deny_header`].
+    pub fn deny_header(mut self, name: impl Into<HeaderName>) -> Self {
+        self.scope = self.scope.deny_header(name);
+        self
+    }
+
+    /// Filters query parameters, see [`SentryScopeMiddleware::sensitive_query_params`].
+    pub fn sensitive_query_params<I, S>(mut self, names: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.scope = self.scope.sensitive_query_params(names);
+        self
+    }
+
+    /// Sets the user, see [`SentryScopeMiddleware::with_user_extractor`].
+    pub fn with_user_extractor<F>(mut self, extractor: F) -> Self
+    where
+        F: Fn(&Request<State>) -> Option<User> + Send + Sync + 'static,
+    {
+        self.scope = self.scope.with_user_extractor(extractor);
+        self
+    }
+
+    /// Configures the request scope, see [`SentryScopeMiddleware::configure_scope`].
+    pub fn configure_scope<F>(mut self, f: F) -> Self
+    where
+        F: Fn(&Request<State>, &mut Scope) + Send + Sync + 'static,
+    {
+        self.scope = self.scope.configure_scope(f);
+        self
+    }
+
+    /// Ignores paths, see [`SentryScopeMiddleware::ignore_paths`].
+    pub fn ignore_paths<I>(mut self, paths: I) -> Self
+    where
+        I: IntoIterator<Item = PathMatcher>,
+    {
+        self.scope = self.scope.ignore_paths(paths);
+        self
+    }
+
+    /// Ignores methods, see [`SentryScopeMiddleware::ignore_methods`].
+    pub fn ignore_methods<I>(mut self, methods: I) -> Self
+    where
+        I: IntoIterator<Item = Method>,
+    {
+        self.scope = self.scope.ignore_methods(methods);
+        self
+    }
+
+    /// Resolves the client address, see [`SentryScopeMiddleware::trusted_proxies`].
+    pub fn trusted_proxies(mut self, proxies: TrustedProxies) -> Self {
+        self.scope = self.scope.trusted_proxies(proxies);
         self
     }
 }
 
-impl Default for SentryMiddleware {
+impl<State> fmt::Debug for SentryMiddleware<State> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SentryMiddleware")
+            .field("scope", &self.scope)
+            .field("capture", &self.capture)
+            .finish()
+    }
+}
+
+impl<State> Default for SentryMiddleware<State> {
     fn default() -> Self {
         Self::new()
     }
 }
 
 #[async_trait::async_trait]
-impl<State> tide::Middleware<State> for SentryMiddleware
+impl<State> tide::Middleware<State> for SentryMiddleware<State>
 where
     State: Clone + Send + Sync + 'static,
 {
     async fn handle(&self, request: Request<State>, next: tide::Next<'_, State>) -> tide::Result {
-        let hub = Arc::new(Hub::new_from_top(Hub::main()));
-        let client = hub.client();
-        let with_pii = client
-            .as_ref()
-            .map_or(false, |x| x.options().send_default_pii);
-
-        let (tx, sentry_req) = sentry_request_from_http(&request, with_pii);
-        hub.configure_scope(|scope| {
-            scope.set_transaction(tx.as_deref());
-            scope.add_event_processor(Box::new(move |event| {
-                Some(process_event(event, &sentry_req))
-            }));
-        });
-
-        let mut response = next.run(request).bind_hub(hub.clone()).await;
-        if self.capture_server_errors && response.status().is_server_error() {
-            if let Some(error) = response.take_error() {
-                let status = error.status();
-                let anyhow_error = error.into_inner();
-                let event_id = hub.capture_anyhow(&anyhow_error);
-
-                if self.emit_header {
-                    response.insert_header("x-sentry-event", event_id.to_simple_ref().to_string());
-                }
-                response.set_error(tide::Error::new(status, anyhow_error));
-            }
-        }
-
-        Ok(response)
+        self.scope
+            .run(request, |request| self.capture.run(request, next))
+            .await
     }
 }
 
-/// Build a Sentry request struct from the HTTP request
-fn sentry_request_from_http<State>(
-    request: &Request<State>,
-    with_pii: bool,
-) -> (Option<String>, SentryRequest) {
-    // TODO: better route information
-    let transaction = Some(request.url().path().to_string());
-
-    let mut sentry_req = SentryRequest {
-        url: Some(request.url().clone()),
-        method: Some(request.method().to_string()),
-        headers: request
-            .iter()
-            .map(|(k, v)| (k.to_string(), v.to_string()))
-            .collect(),
+/// Build a breadcrumb of the start of the HTTP request
+fn http_breadcrumb(request: &SentryRequest) -> Breadcrumb {
+    let mut breadcrumb = Breadcrumb {
+        ty: "http".into(),
+        category: Some("http".into()),
         ..Default::default()
     };
+    if let Some(method) = &request.method {
+        breadcrumb
+            .data
+            .insert("method".into(), method.clone().into());
+    }
+    if let Some(url) = &request.url {
+        breadcrumb.data.insert("url".into(), url.to_string().into());
+    }
+    breadcrumb
+}
 
-    // If PII is enabled, include the remote address
-    if with_pii {
-        if let Some(remote) = request.remote() {
-            sentry_req.env.insert("REMOTE_ADDR".into(), remote.into());
-        }
+/// Build a breadcrumb of the response to the HTTP request from the breadcrumb of its start
+fn http_response_breadcrumb(
+    mut breadcrumb: Breadcrumb,
+    status: StatusCode,
+    duration: Duration,
+) -> Breadcrumb {
+    breadcrumb.timestamp = SystemTime::now();
+    breadcrumb.level = if status.is_server_error() {
+        Level::Error
+    } else if status.is_client_error() {
+        Level::Warning
+    } else {
+        Level::Info
     };
+    breadcrumb
+        .data
+        .insert("status_code".into(), (status as u16).into());
+    breadcrumb.data.insert(
+        "duration_ms".into(),
+        Value::from(duration.as_secs_f64() * 1000.0),
+    );
+    breadcrumb
+}
 
-    (transaction, sentry_req)
+/// Map the HTTP status of a response to the status of a Sentry transaction
+fn map_status(status: StatusCode) -> SpanStatus {
+    match status {
+        StatusCode::Unauthorized => SpanStatus::Unauthenticated,
+        StatusCode::Forbidden => SpanStatus::PermissionDenied,
+        StatusCode::NotFound => SpanStatus::NotFound,
+        StatusCode::Conflict => SpanStatus::AlreadyExists,
+        StatusCode::TooManyRequests => SpanStatus::ResourceExhausted,
+        StatusCode::NotImplemented => SpanStatus::Unimplemented,
+        StatusCode::ServiceUnavailable => SpanStatus::Unavailable,
+        StatusCode::GatewayTimeout => SpanStatus::DeadlineExceeded,
+        status if status.is_client_error() => SpanStatus::InvalidArgument,
+        status if status.is_server_error() => SpanStatus::InternalError,
+        _ => SpanStatus::Ok,
+    }
 }
 
 /// Add request data to a Sentry event
@@ -128,6 +305,18 @@ fn process_event(mut event: Event<'static>, request: &SentryRequest) -> Event<'s
         event.request = Some(request.clone());
     }
 
+    // User
+    if let Some(ip) = request
+        .env
+        .get("REMOTE_ADDR")
+        .and_then(|x| proxy::parse_ip(x))
+    {
+        let user = event.user.get_or_insert_with(Default::default);
+        if user.ip_address.is_none() {
+            user.ip_address = Some(IpAddress::Exact(ip));
+        }
+    }
+
     // SDK
     if let Some(sdk) = event.sdk.take() {
         let mut sdk = sdk.into_owned();
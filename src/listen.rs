@@ -0,0 +1,107 @@
+use std::io;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use async_signal::{Signal, Signals};
+use async_std::task;
+use futures_lite::{future, StreamExt};
+use sentry_core::{Client, Hub};
+use tide::listener::ToListener;
+use tide::{Next, Request, Server};
+
+/// How often to check whether the in-flight requests are done
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// Serves the app like [`Server::listen`] until `SIGINT` or `SIGTERM`, then flushes the events
+/// of the last requests.
+///
+/// On a signal, the server stops accepting connections and waits for the requests counted by
+/// `in_flight`, then closes the clients of the given hubs so that their pending events are sent.
+/// Both are bounded by the given timeout.
+///
+/// `in_flight` must be the first middleware of the app, so that it counts the requests until
+/// all the middlewares which may capture events are done. The hubs are those these middlewares
+/// capture on, e.g. the hub given to
+/// [`SentryMiddleware::with_hub`](crate::SentryMiddleware::with_hub), or [`Hub::main`] for the
+/// default hub.
+pub async fn listen_with_flush<State, L, H>(
+    app: Server<State>,
+    listener: L,
+    in_flight: InFlightRequests,
+    hubs: H,
+    timeout: Duration,
+) -> io::Result<()>
+where
+    State: Clone + Send + Sync + 'static,
+    L: ToListener<State>,
+    H: IntoIterator<Item = Arc<Hub>>,
+{
+    let mut clients: Vec<Arc<Client>> = Vec::new();
+    for client in hubs.into_iter().filter_map(|hub| hub.client()) {
+        // Hubs may share a client, which is only closed once
+        if !clients.iter().any(|x| Arc::ptr_eq(x, &client)) {
+            clients.push(client);
+        }
+    }
+
+    let mut signals = Signals::new([Signal::Int, Signal::Term])?;
+    let shutdown = async {
+        signals.next().await;
+        Ok(())
+    };
+    // Dropping the listen future stops accepting connections
+    future::or(app.listen(listener), shutdown).await?;
+
+    let deadline = Instant::now() + timeout;
+    while in_flight.count() > 0 && Instant::now() < deadline {
+        task::sleep(POLL_INTERVAL).await;
+    }
+
+    for client in clients {
+        let timeout = deadline.saturating_duration_since(Instant::now());
+        // Closing blocks the thread until the events are sent
+        task::spawn_blocking(move || client.close(Some(timeout))).await;
+    }
+
+    Ok(())
+}
+
+/// Middleware which counts the in-flight requests, for [`listen_with_flush`] to wait for them.
+///
+/// Clones share the same count.
+#[derive(Debug, Clone, Default)]
+pub struct InFlightRequests(Arc<AtomicUsize>);
+
+impl InFlightRequests {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the number of requests being handled.
+    pub fn count(&self) -> usize {
+        self.0.load(Ordering::SeqCst)
+    }
+}
+
+/// Decrements the count of in-flight requests when dropped, even if the request panics
+struct InFlightGuard(Arc<AtomicUsize>);
+
+impl Drop for InFlightGuard {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+#[async_trait::async_trait]
+impl<State> tide::Middleware<State> for InFlightRequests
+where
+    State: Clone + Send + Sync + 'static,
+{
+    async fn handle(&self, request: Request<State>, next: Next<'_, State>) -> tide::Result {
+        self.0.fetch_add(1, Ordering::SeqCst);
+        let _guard = InFlightGuard(self.0.clone());
+
+        Ok(next.run(request).await)
+    }
+}
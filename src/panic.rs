@@ -0,0 +1,158 @@
+use std::any::Any;
+use std::cell::{Cell, RefCell};
+use std::future::Future;
+use std::panic::{self, AssertUnwindSafe};
+use std::pin::Pin;
+use std::sync::Once;
+use std::task::{Context, Poll};
+
+use sentry_backtrace::current_stacktrace;
+use sentry_core::protocol::{Event, Exception, Level, Mechanism, Stacktrace};
+use sentry_core::types::Uuid;
+
+static INIT: Once = Once::new();
+
+/// Frames of the panic machinery, which are trimmed from the stacktrace
+const PANIC_FRAMES: &[&str] = &["std::panicking::", "core::panicking::", "rust_begin_unwind"];
+/// Frames of the standard library, which may be interleaved with the panic machinery
+const SYS_FRAMES: &[&str] = &["std::", "core::", "alloc::", "__rust"];
+
+thread_local! {
+    /// How many `CatchPanic` futures are being polled on this thread
+    static CATCHING: Cell<usize> = const { Cell::new(0) };
+    /// The stacktrace of the last panic caught by a `CatchPanic` future on this thread
+    static STACKTRACE: RefCell<Option<Stacktrace>> = const { RefCell::new(None) };
+    /// The ID of the event another panic hook captured for the last panic caught by a
+    /// `CatchPanic` future on this thread
+    static HOOK_EVENT: Cell<Option<Uuid>> = const { Cell::new(None) };
+}
+
+/// Install a panic hook which records the stacktrace of panics caught by `CatchPanic`
+///
+/// The stacktrace is gone once the panic unwound to `catch_unwind`, so it has to be recorded
+/// while panicking. The previous hook is still called.
+pub(crate) fn install_hook() {
+    INIT.call_once(|| {
+        let next = panic::take_hook();
+        panic::set_hook(Box::new(move |info| {
+            if CATCHING.with(|x| x.get()) > 0 {
+                let stacktrace = current_stacktrace().map(trim_stacktrace);
+                STACKTRACE.with(|x| *x.borrow_mut() = stacktrace);
+            }
+            next(info);
+        }));
+    });
+}
+
+/// Event processor which records the panic events captured by other panic hooks while a
+/// `CatchPanic` future is polled, e.g. by the panic integration of the `sentry` crate
+///
+/// Those hooks capture on the hub bound to the future, so the panic is already reported once it
+/// is caught.
+pub(crate) fn record_hook_event(event: Event<'static>) -> Option<Event<'static>> {
+    let is_panic = event
+        .exception
+        .iter()
+        .any(|x| x.mechanism.as_ref().is_some_and(|x| x.ty == "panic"));
+    if is_panic && CATCHING.with(|x| x.get()) > 0 {
+        HOOK_EVENT.with(|x| x.set(Some(event.event_id)));
+    }
+    Some(event)
+}
+
+/// Trim the frames of the panic hook and the panic machinery from the stacktrace
+fn trim_stacktrace(mut stacktrace: Stacktrace) -> Stacktrace {
+    let starts_with_any = |func: &str, prefixes: &[&str]| {
+        let func = func.trim_start_matches('<');
+        prefixes.iter().any(|x| func.starts_with(x))
+    };
+
+    // Frames are ordered from the outermost to the innermost call
+    let mut cutoff = None;
+    for (i, frame) in stacktrace.frames.iter().enumerate().rev() {
+        let func = frame.function.as_deref().unwrap_or_default();
+        if starts_with_any(func, PANIC_FRAMES) {
+            cutoff = Some(i);
+        } else if cutoff.is_some() && !starts_with_any(func, SYS_FRAMES) {
+            break;
+        }
+    }
+    if let Some(cutoff) = cutoff {
+        stacktrace.frames.truncate(cutoff);
+    }
+    stacktrace
+}
+
+/// A panic caught by `CatchPanic`
+pub(crate) struct Panic {
+    payload: Box<dyn Any + Send>,
+    stacktrace: Option<Stacktrace>,
+    /// The ID of the event another panic hook captured for the panic, if any
+    pub(crate) event_id: Option<Uuid>,
+}
+
+impl Panic {
+    fn message(&self) -> &str {
+        match self.payload.downcast_ref::<&'static str>() {
+            Some(s) => s,
+            None => match self.payload.downcast_ref::<String>() {
+                Some(s) => s,
+                None => "Box<Any>",
+            },
+        }
+    }
+
+    /// Build an unhandled exception event of the panic
+    pub(crate) fn to_event(&self) -> Event<'static> {
+        Event {
+            exception: vec![Exception {
+                ty: "panic".into(),
+                value: Some(self.message().to_string()),
+                mechanism: Some(Mechanism {
+                    ty: "panic".into(),
+                    handled: Some(false),
+                    ..Default::default()
+                }),
+                stacktrace: self.stacktrace.clone(),
+                ..Default::default()
+            }]
+            .into(),
+            level: Level::Fatal,
+            ..Default::default()
+        }
+    }
+}
+
+/// Future which catches panics of the inner future
+pub(crate) struct CatchPanic<'a, T> {
+    future: Pin<Box<dyn Future<Output = T> + Send + 'a>>,
+}
+
+impl<'a, T> CatchPanic<'a, T> {
+    pub(crate) fn new(future: impl Future<Output = T> + Send + 'a) -> Self {
+        Self {
+            future: Box::pin(future),
+        }
+    }
+}
+
+impl<'a, T> Future for CatchPanic<'a, T> {
+    type Output = Result<T, Panic>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let future = self.future.as_mut();
+
+        CATCHING.with(|x| x.set(x.get() + 1));
+        let result = panic::catch_unwind(AssertUnwindSafe(|| future.poll(cx)));
+        CATCHING.with(|x| x.set(x.get() - 1));
+
+        match result {
+            Ok(poll) => poll.map(Ok),
+            Err(payload) => Poll::Ready(Err(Panic {
+                payload,
+                stacktrace: STACKTRACE.with(|x| x.borrow_mut().take()),
+                event_id: HOOK_EVENT.with(|x| x.take()),
+            })),
+        }
+    }
+}
@@ -0,0 +1,217 @@
+use std::collections::BTreeMap;
+#[cfg(feature = "surf")]
+use std::{
+    cell::RefCell,
+    future::Future,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+};
+
+use percent_encoding::percent_decode_str;
+use sentry_core::protocol::{SpanId, TraceContext, TraceId, Value};
+use sentry_core::TransactionContext;
+use tide::Request;
+
+pub(crate) const SENTRY_TRACE_HEADER: &str = "sentry-trace";
+pub(crate) const BAGGAGE_HEADER: &str = "baggage";
+pub(crate) const BAGGAGE_SENTRY_PREFIX: &str = "sentry-";
+
+/// The `sentry-trace` and `baggage` headers of an incoming request
+///
+/// The headers are parsed once, for the transaction, the scope and the outgoing requests of the
+/// request.
+#[derive(Debug)]
+pub(crate) struct TraceHeaders {
+    /// The `sentry-trace` header, if it is valid
+    sentry_trace: Option<SentryTrace>,
+    /// The `sentry-` members of the `baggage` headers, as received
+    baggage_members: Vec<String>,
+}
+
+#[derive(Debug)]
+struct SentryTrace {
+    trace_id: TraceId,
+    parent_span_id: SpanId,
+    /// The header, as received
+    header: String,
+}
+
+impl SentryTrace {
+    fn parse(header: &str) -> Option<Self> {
+        let header = header.trim();
+        let mut parts = header.splitn(3, '-');
+        let trace_id = parts.next()?.parse().ok()?;
+        let parent_span_id = parts.next()?.parse().ok()?;
+
+        Some(Self {
+            trace_id,
+            parent_span_id,
+            header: header.to_string(),
+        })
+    }
+}
+
+impl TraceHeaders {
+    pub(crate) fn from_http<State>(request: &Request<State>) -> Self {
+        let sentry_trace = request
+            .header(SENTRY_TRACE_HEADER)
+            .and_then(|x| SentryTrace::parse(x.as_str()));
+        let baggage_members = request
+            .header(BAGGAGE_HEADER)
+            .into_iter()
+            .flatten()
+            .flat_map(|x| x.as_str().split(','))
+            .map(str::trim)
+            .filter(|x| x.starts_with(BAGGAGE_SENTRY_PREFIX))
+            .map(str::to_string)
+            .collect();
+
+        Self {
+            sentry_trace,
+            baggage_members,
+        }
+    }
+
+    /// Build a transaction context which continues the incoming trace
+    ///
+    /// The dynamic sampling context carried by `baggage` is available to samplers under the
+    /// `baggage` key of the custom context.
+    pub(crate) fn transaction_context(&self, name: &str, op: &str) -> TransactionContext {
+        // The context can only continue a trace from the header
+        let mut ctx = TransactionContext::continue_from_headers(
+            name,
+            op,
+            self.sentry_trace
+                .as_ref()
+                .map(|x| (SENTRY_TRACE_HEADER, x.header.as_str())),
+        );
+
+        let dsc = self.dynamic_sampling_context();
+        if self.sentry_trace.is_some() && ctx.sampled().is_none() {
+            // Fall back to the sampling decision of the dynamic sampling context
+            match dsc.get("sampled").map(String::as_str) {
+                Some("true") => ctx.set_sampled(true),
+                Some("false") => ctx.set_sampled(false),
+                _ => {}
+            }
+        }
+        if !dsc.is_empty() {
+            let dsc = dsc.into_iter().map(|(k, v)| (k, Value::from(v))).collect();
+            ctx.custom_insert(BAGGAGE_HEADER.into(), dsc);
+        }
+
+        ctx
+    }
+
+    /// Build the trace context of a request which is not traced by a transaction
+    ///
+    /// Returns `None` if the request does not continue a trace.
+    pub(crate) fn trace_context(&self) -> Option<TraceContext> {
+        let sentry_trace = self.sentry_trace.as_ref()?;
+
+        Some(TraceContext {
+            trace_id: sentry_trace.trace_id,
+            parent_span_id: Some(sentry_trace.parent_span_id),
+            op: Some("http.server".into()),
+            ..Default::default()
+        })
+    }
+
+    /// Returns the trace to propagate to outgoing requests, if the request continues one
+    #[cfg(feature = "surf")]
+    pub(crate) fn into_incoming_trace(self) -> Option<IncomingTrace> {
+        let sentry_trace = self.sentry_trace?;
+        let members = self.baggage_members;
+
+        Some(IncomingTrace {
+            trace_id: sentry_trace.trace_id,
+            sentry_trace: sentry_trace.header,
+            dynamic_sampling_context: (!members.is_empty()).then(|| members.join(",")),
+        })
+    }
+
+    /// Collect the `sentry-` members of the `baggage` headers, without the prefix
+    fn dynamic_sampling_context(&self) -> BTreeMap<String, String> {
+        let mut dsc = BTreeMap::new();
+        for member in &self.baggage_members {
+            // Drop the member properties
+            let member = member.split(';').next().unwrap_or_default().trim();
+            let (key, value) = match member.split_once('=') {
+                Some(pair) => pair,
+                None => continue,
+            };
+            if let Some(key) = key.trim().strip_prefix(BAGGAGE_SENTRY_PREFIX) {
+                let value = percent_decode_str(value.trim()).decode_utf8_lossy();
+                dsc.insert(key.to_string(), value.into_owned());
+            }
+        }
+        dsc
+    }
+}
+
+#[cfg(feature = "surf")]
+thread_local! {
+    /// The trace of the request whose future is being polled
+    static INCOMING_TRACE: RefCell<Option<Arc<IncomingTrace>>> = const { RefCell::new(None) };
+}
+
+/// The trace an incoming request continues, to be propagated to outgoing requests
+#[cfg(feature = "surf")]
+#[derive(Debug)]
+pub(crate) struct IncomingTrace {
+    pub(crate) trace_id: TraceId,
+    /// The `sentry-trace` header, as received
+    pub(crate) sentry_trace: String,
+    /// The `sentry-` members of the `baggage` headers, as received
+    pub(crate) dynamic_sampling_context: Option<String>,
+}
+
+#[cfg(feature = "surf")]
+impl IncomingTrace {
+    /// Returns the trace of the request whose future is being polled, if it continues one
+    pub(crate) fn current() -> Option<Arc<Self>> {
+        INCOMING_TRACE.with(|x| x.borrow().clone())
+    }
+}
+
+/// Future which makes the trace of its request available to outgoing requests while it is
+/// polled
+#[cfg(feature = "surf")]
+pub(crate) struct BindIncomingTrace<'a, T> {
+    future: Pin<Box<dyn Future<Output = T> + Send + 'a>>,
+    trace: Option<Arc<IncomingTrace>>,
+}
+
+#[cfg(feature = "surf")]
+impl<'a, T> BindIncomingTrace<'a, T> {
+    pub(crate) fn new(
+        future: impl Future<Output = T> + Send + 'a,
+        trace: Option<IncomingTrace>,
+    ) -> Self {
+        Self {
+            future: Box::pin(future),
+            trace: trace.map(Arc::new),
+        }
+    }
+}
+
+#[cfg(feature = "surf")]
+impl<'a, T> Future for BindIncomingTrace<'a, T> {
+    type Output = T;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        /// Restores the previous trace, even if the future panics
+        struct Guard(Option<Arc<IncomingTrace>>);
+
+        impl Drop for Guard {
+            fn drop(&mut self) {
+                INCOMING_TRACE.with(|x| *x.borrow_mut() = self.0.take());
+            }
+        }
+
+        let previous = INCOMING_TRACE.with(|x| x.replace(self.trace.clone()));
+        let _guard = Guard(previous);
+        self.future.as_mut().poll(cx)
+    }
+}
@@ -0,0 +1,129 @@
+use std::net::{IpAddr, SocketAddr};
+
+use ipnet::IpNet;
+use tide::http::headers::FORWARDED;
+use tide::http::proxies::Forwarded;
+use tide::Request;
+
+const X_FORWARDED_FOR: &str = "x-forwarded-for";
+
+/// Decides which hop of the `Forwarded` or `X-Forwarded-For` header is the client.
+///
+/// Without it, [`SentryMiddleware`] trusts the first forwarded hop, which clients can spoof.
+///
+/// Only the header written by the proxies is read, see [`header`](Self::header). The other one
+/// is ignored, as it may come from the client.
+///
+/// [`SentryMiddleware`]: crate::SentryMiddleware
+#[derive(Debug, Clone)]
+pub struct TrustedProxies {
+    trust: Trust,
+    header: ForwardedHeader,
+}
+
+#[derive(Debug, Clone)]
+enum Trust {
+    Hops(usize),
+    Networks(Vec<IpNet>),
+}
+
+/// The header the trusted proxies append the address of their peer to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ForwardedHeader {
+    /// The standard `Forwarded` header.
+    Forwarded,
+    /// The `X-Forwarded-For` header.
+    #[default]
+    XForwardedFor,
+}
+
+impl TrustedProxies {
+    /// Trusts the given number of proxies in front of the server.
+    ///
+    /// The client is the hop added by the outermost trusted proxy. With `0`, the client is the
+    /// peer address and the forwarded headers are ignored.
+    pub fn hops(count: usize) -> Self {
+        Self {
+            trust: Trust::Hops(count),
+            header: ForwardedHeader::default(),
+        }
+    }
+
+    /// Trusts the proxies in the given networks.
+    ///
+    /// The client is the last hop which is not in one of the networks.
+    pub fn networks<I>(networks: I) -> Self
+    where
+        I: IntoIterator<Item = IpNet>,
+    {
+        Self {
+            trust: Trust::Networks(networks.into_iter().collect()),
+            header: ForwardedHeader::default(),
+        }
+    }
+
+    /// Reads the hops from the given header, which the proxies write.
+    ///
+    /// The default is `X-Forwarded-For`.
+    pub fn header(mut self, header: ForwardedHeader) -> Self {
+        self.header = header;
+        self
+    }
+
+    /// Resolve the client address of the request
+    pub(crate) fn client_ip<State>(&self, request: &Request<State>) -> Option<IpAddr> {
+        // Hops ordered from the client to the peer, which are unparsable if obfuscated
+        let mut chain: Vec<_> = match self.header {
+            ForwardedHeader::Forwarded => request
+                .header(FORWARDED)
+                .into_iter()
+                .flatten()
+                .flat_map(|x| match Forwarded::parse(x.as_str()) {
+                    Ok(forwarded) => forwarded
+                        .forwarded_for()
+                        .into_iter()
+                        .map(parse_ip)
+                        .collect(),
+                    // A malformed header cannot be trusted as a whole
+                    Err(_) => vec![None],
+                })
+                .collect(),
+            ForwardedHeader::XForwardedFor => request
+                .header(X_FORWARDED_FOR)
+                .into_iter()
+                .flatten()
+                .flat_map(|x| x.as_str().split(','))
+                .map(parse_ip)
+                .collect(),
+        };
+        chain.push(request.peer_addr().and_then(parse_ip));
+
+        match &self.trust {
+            Trust::Hops(count) => {
+                let index = chain.len().saturating_sub(count.saturating_add(1));
+                chain[index]
+            }
+            Trust::Networks(networks) => {
+                let is_trusted = |ip: &IpAddr| networks.iter().any(|x| x.contains(ip));
+                let index = chain
+                    .iter()
+                    .rposition(|x| !x.as_ref().is_some_and(is_trusted))
+                    .unwrap_or(0);
+                chain[index]
+            }
+        }
+    }
+}
+
+/// Parse an IP address, which may come with a port
+pub(crate) fn parse_ip(addr: &str) -> Option<IpAddr> {
+    let addr = addr.trim().trim_matches('"');
+    addr.parse::<IpAddr>()
+        .or_else(|_| addr.parse::<SocketAddr>().map(|x| x.ip()))
+        .ok()
+        .or_else(|| {
+            // IPv6 addresses are bracketed in the `Forwarded` header
+            let addr = addr.strip_prefix('[')?;
+            addr[..addr.find(']')?].parse().ok()
+        })
+}
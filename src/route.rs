@@ -0,0 +1,70 @@
+use tide::{Next, Request, Route, Server};
+
+use crate::ext::RequestHub;
+
+/// The route pattern that matched a request, e.g. `/users/:id`.
+///
+/// Inserted into the request extensions by [`SentryRouteMiddleware`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MatchedRoute(String);
+
+impl MatchedRoute {
+    /// Returns the route pattern.
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+/// Route-level middleware that names the Sentry transaction after a route pattern.
+///
+/// Tide does not expose the pattern a request was routed by, so [`SentryMiddleware`] can only
+/// name the transaction after the raw URL path. Attach this middleware to a route to use the
+/// pattern instead, so `/users/1` and `/users/2` are grouped into the same transaction.
+///
+/// Requests which do not pass through the scope middleware, e.g. ignored ones, are left as is.
+///
+/// [`SentryMiddleware`]: crate::SentryMiddleware
+#[derive(Debug, Clone)]
+pub struct SentryRouteMiddleware {
+    pattern: String,
+}
+
+impl SentryRouteMiddleware {
+    pub fn new(pattern: impl Into<String>) -> Self {
+        Self {
+            pattern: pattern.into(),
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl<State> tide::Middleware<State> for SentryRouteMiddleware
+where
+    State: Clone + Send + Sync + 'static,
+{
+    async fn handle(&self, mut request: Request<State>, next: Next<'_, State>) -> tide::Result {
+        // Without the scope middleware, e.g. for ignored requests, there is no scope of the
+        // request to name, and the current hub outlives the request
+        if let Some(request_hub) = request.ext::<RequestHub>() {
+            let pattern = &self.pattern;
+            request_hub
+                .hub
+                .configure_scope(|scope| scope.set_transaction(Some(pattern)));
+        }
+        request.set_ext(MatchedRoute(self.pattern.clone()));
+
+        Ok(next.run(request).await)
+    }
+}
+
+/// Adds a route to the server, like [`Server::at`], which names the Sentry transaction after
+/// its pattern.
+pub fn route<'a, State>(server: &'a mut Server<State>, path: &str) -> Route<'a, State>
+where
+    State: Clone + Send + Sync + 'static,
+{
+    let mut route = server.at(path);
+    let pattern = route.path().to_string();
+    route.with(SentryRouteMiddleware::new(pattern));
+    route
+}
@@ -0,0 +1,523 @@
+use std::fmt;
+use std::future::Future;
+use std::sync::Arc;
+
+use sentry_core::protocol::{Request as SentryRequest, User};
+use sentry_core::{Hub, Scope, SentryFutureExt, TracesSampler, TransactionContext};
+use tide::http::headers::{HeaderName, COOKIE};
+use tide::http::Method;
+use tide::Request;
+
+use crate::body::{self, MaxRequestBodySize};
+use crate::ext::RequestHub;
+use crate::ignore::PathMatcher;
+#[cfg(feature = "surf")]
+use crate::propagation::BindIncomingTrace;
+use crate::propagation::TraceHeaders;
+use crate::proxy::TrustedProxies;
+use crate::scrub::{self, HeaderFilter};
+use crate::session;
+use crate::{http_breadcrumb, map_status, process_event};
+
+type UserExtractor<State> = dyn Fn(&Request<State>) -> Option<User> + Send + Sync;
+type ScopeConfigurator<State> = dyn Fn(&Request<State>, &mut Scope) + Send + Sync;
+type HubSelector<State> = dyn Fn(&Request<State>) -> Arc<Hub> + Send + Sync;
+
+/// The hub the hub of each request is derived from
+enum ParentHub<State> {
+    Main,
+    Fixed(Arc<Hub>),
+    Selector(Arc<HubSelector<State>>),
+}
+
+impl<State> ParentHub<State> {
+    fn select(&self, request: &Request<State>) -> Arc<Hub> {
+        match self {
+            Self::Main => Hub::main(),
+            Self::Fixed(hub) => hub.clone(),
+            Self::Selector(selector) => selector(request),
+        }
+    }
+}
+
+impl<State> fmt::Debug for ParentHub<State> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Main => f.write_str("Main"),
+            Self::Fixed(hub) => f.debug_tuple("Fixed").field(hub).finish(),
+            Self::Selector(_) => f.write_str("Selector"),
+        }
+    }
+}
+
+/// Middleware which binds a hub to each request, and sets up its scope and transaction.
+///
+/// It does not capture errors, which is the job of [`SentryCaptureMiddleware`]. The capture
+/// middleware finds the hub of the request in the request extensions, so middlewares which
+/// render errors can be placed between the two. [`SentryMiddleware`] combines both.
+///
+/// [`SentryCaptureMiddleware`]: crate::SentryCaptureMiddleware
+/// [`SentryMiddleware`]: crate::SentryMiddleware
+pub struct SentryScopeMiddleware<State> {
+    hub: ParentHub<State>,
+    client_error_breadcrumbs: bool,
+    start_transaction: bool,
+    start_session: bool,
+    traces_sampler: Option<Arc<TracesSampler>>,
+    max_request_body_size: MaxRequestBodySize,
+    header_filter: HeaderFilter,
+    sensitive_query_params: Vec<String>,
+    user_extractor: Option<Arc<UserExtractor<State>>>,
+    scope_configurator: Option<Arc<ScopeConfigurator<State>>>,
+    ignored_paths: Vec<PathMatcher>,
+    ignored_methods: Vec<Method>,
+    trusted_proxies: Option<TrustedProxies>,
+}
+
+impl<State> SentryScopeMiddleware<State> {
+    pub fn new() -> Self {
+        Self {
+            hub: ParentHub::Main,
+            client_error_breadcrumbs: false,
+            start_transaction: true,
+            start_session: true,
+            traces_sampler: None,
+            max_request_body_size: MaxRequestBodySize::None,
+            header_filter: HeaderFilter::default(),
+            sensitive_query_params: scrub::SENSITIVE_QUERY_PARAMS
+                .iter()
+                .map(|x| x.to_string())
+                .collect(),
+            user_extractor: None,
+            scope_configurator: None,
+            ignored_paths: Vec::new(),
+            ignored_methods: Vec::new(),
+            trusted_proxies: None,
+        }
+    }
+
+    /// Reconfigures the middleware so that it uses a specific hub instead of the default one.
+    pub fn with_hub(mut self, hub: Arc<Hub>) -> Self {
+        self.hub = ParentHub::Fixed(hub);
+        self
+    }
+
+    /// Reconfigures the middleware so that it uses the main hub for every request.
+    pub fn with_default_hub(mut self) -> Self {
+        self.hub = ParentHub::Main;
+        self
+    }
+
+    /// Reconfigures the middleware so that it picks the hub of each request with the given
+    /// closure, e.g. to report the requests of each tenant to its own project.
+    ///
+    /// The closure is called before the request is passed on, so it can use the `Host` header,
+    /// the path or the extensions set by outer middlewares. The hub of the request is derived from
+    /// the returned hub.
+    pub fn with_hub_selector<F>(mut self, selector: F) -> Self
+    where
+        F: Fn(&Request<State>) -> Arc<Hub> + Send + Sync + 'static,
+    {
+        self.hub = ParentHub::Selector(Arc::new(selector));
+        self
+    }
+
+    /// Enables or disables recording a breadcrumb on the parent hub for 4xx responses whose
+    /// error is not captured.
+    ///
+    /// This gives later events of other requests context about recent client errors. The
+    /// default is to only record breadcrumbs on the request scope.
+    pub fn client_error_breadcrumbs(mut self, val: bool) -> Self {
+        self.client_error_breadcrumbs = val;
+        self
+    }
+
+    /// Enables or disables starting a performance transaction for each request.
+    ///
+    /// The default is to start a transaction, which is then sampled according to the client's
+    /// `traces_sample_rate` or `traces_sampler`.
+    pub fn start_transaction(mut self, val: bool) -> Self {
+        self.start_transaction = val;
+        self
+    }
+
+    /// Enables or disables tracking each request as a release health session.
+    ///
+    /// Sessions are only tracked if the client's `auto_session_tracking` option is enabled, its
+    /// `session_mode` is `SessionMode::Request` and a `release` is configured. The client then
+    /// aggregates them before sending them periodically. A session is crashed if the request
+    /// panics, and errored if an error is captured. The default is to track sessions.
+    ///
+    /// To count the error of a response on the session without crashing it, an empty error-level
+    /// event runs through the request scope before the error is captured. That event is never
+    /// sent, but the event processors of the scope see it.
+    pub fn start_session(mut self, val: bool) -> Self {
+        self.start_session = val;
+        self
+    }
+
+    /// Reconfigures the middleware so that it samples transactions with the given closure instead
+    /// of the client options.
+    ///
+    /// The closure returns the sample rate of the transaction, between `0.0` and `1.0`. It is not
+    /// called for requests which carry the sampling decision of an upstream service.
+    pub fn with_traces_sampler<F>(mut self, sampler: F) -> Self
+    where
+        F: Fn(&TransactionContext) -> f32 + Send + Sync + 'static,
+    {
+        self.traces_sampler = Some(Arc::new(sampler));
+        self
+    }
+
+    /// Configures how much of the request body is attached to captured events.
+    ///
+    /// The body is buffered up to this size and handed to the endpoint unchanged. The default is
+    /// to never attach the body.
+    pub fn max_request_body_size(mut self, val: MaxRequestBodySize) -> Self {
+        self.max_request_body_size = val;
+        self
+    }
+
+    /// Attaches the given header to events even if PII is disabled.
+    ///
+    /// By default, headers which may carry credentials or client addresses, like `Authorization`
+    /// and `Cookie`, are only attached if the client's `send_default_pii` option is enabled.
+    pub fn allow_header(mut self, name: impl Into<HeaderName>) -> Self {
+        self.header_filter.allow(name.into());
+        self
+    }
+
+    /// Never attaches the given header to events, even if PII is enabled.
+    pub fn deny_header(mut self, name: impl Into<HeaderName>) -> Self {
+        self.header_filter.deny(name.into());
+        self
+    }
+
+    /// Replaces the list of sensitive query parameters, whose values are filtered out of the URL
+    /// and query string attached to events.
+    ///
+    /// A parameter is sensitive if its name contains one of the given names, ignoring case. The
+    /// default list contains names like `token`, `password` and `secret`.
+    pub fn sensitive_query_params<I, S>(mut self, names: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.sensitive_query_params = names
+            .into_iter()
+            .map(|x| x.into().to_ascii_lowercase())
+            .collect();
+        self
+    }
+
+    /// Sets the user of the request scope with the given closure.
+    ///
+    /// The closure is called before the request is passed on, so it sees the extensions set by
+    /// outer middlewares. Request extensions set by inner middlewares, e.g. for authentication,
+    /// are not visible to it. Those middlewares can insert a `User` into the response extensions
+    /// instead, which takes precedence once the response is produced.
+    pub fn with_user_extractor<F>(mut self, extractor: F) -> Self
+    where
+        F: Fn(&Request<State>) -> Option<User> + Send + Sync + 'static,
+    {
+        self.user_extractor = Some(Arc::new(extractor));
+        self
+    }
+
+    /// Configures the request scope with the given closure, e.g. to add tags from the app state
+    /// or the request headers.
+    ///
+    /// The closure is called after the request data is set on the scope, before the request is
+    /// passed on.
+    pub fn configure_scope<F>(mut self, f: F) -> Self
+    where
+        F: Fn(&Request<State>, &mut Scope) + Send + Sync + 'static,
+    {
+        self.scope_configurator = Some(Arc::new(f));
+        self
+    }
+
+    /// Ignores requests whose path matches one of the given matchers.
+    ///
+    /// Ignored requests are passed on without creating a hub, starting a transaction or capturing
+    /// errors, e.g. for health checks and static assets.
+    pub fn ignore_paths<I>(mut self, paths: I) -> Self
+    where
+        I: IntoIterator<Item = PathMatcher>,
+    {
+        self.ignored_paths.extend(paths);
+        self
+    }
+
+    /// Ignores requests with one of the given methods, like [`ignore_paths`](Self::ignore_paths).
+    pub fn ignore_methods<I>(mut self, methods: I) -> Self
+    where
+        I: IntoIterator<Item = Method>,
+    {
+        self.ignored_methods.extend(methods);
+        self
+    }
+
+    /// Resolves the client address from the forwarded headers of the given proxies only.
+    ///
+    /// The address is attached as `REMOTE_ADDR` and the user IP address if PII is enabled. By
+    /// default, the first hop of the `Forwarded` or `X-Forwarded-For` headers is used.
+    pub fn trusted_proxies(mut self, proxies: TrustedProxies) -> Self {
+        self.trusted_proxies = Some(proxies);
+        self
+    }
+
+    fn is_ignored(&self, request: &Request<State>) -> bool {
+        self.ignored_methods.contains(&request.method())
+            || self
+                .ignored_paths
+                .iter()
+                .any(|x| x.is_match(request.url().path()))
+    }
+}
+
+impl<State> fmt::Debug for SentryScopeMiddleware<State> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SentryScopeMiddleware")
+            .field("hub", &self.hub)
+            .field("client_error_breadcrumbs", &self.client_error_breadcrumbs)
+            .field("start_transaction", &self.start_transaction)
+            .field("start_session", &self.start_session)
+            .field("traces_sampler", &self.traces_sampler.is_some())
+            .field("max_request_body_size", &self.max_request_body_size)
+            .field("header_filter", &self.header_filter)
+            .field("sensitive_query_params", &self.sensitive_query_params)
+            .field("user_extractor", &self.user_extractor.is_some())
+            .field("scope_configurator", &self.scope_configurator.is_some())
+            .field("ignored_paths", &self.ignored_paths)
+            .field("ignored_methods", &self.ignored_methods)
+            .field("trusted_proxies", &self.trusted_proxies)
+            .finish()
+    }
+}
+
+impl<State> Default for SentryScopeMiddleware<State> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<State> SentryScopeMiddleware<State>
+where
+    State: Clone + Send + Sync + 'static,
+{
+    /// Set up the hub of the request, and pass the request on to `inner` with the hub bound
+    pub(crate) async fn run<F, Fut>(&self, mut request: Request<State>, inner: F) -> tide::Result
+    where
+        F: FnOnce(Request<State>) -> Fut + Send,
+        Fut: Future<Output = tide::Result> + Send,
+    {
+        if self.is_ignored(&request) {
+            return inner(request).await;
+        }
+
+        let parent_hub = self.hub.select(&request);
+        let hub = Arc::new(Hub::new_from_top(&parent_hub));
+        let client = hub.client();
+        let with_pii = client
+            .as_ref()
+            .is_some_and(|x| x.options().send_default_pii);
+
+        let (tx, mut sentry_req) = sentry_request_from_http(&request, with_pii, self);
+        let trace_headers = TraceHeaders::from_http(&request);
+        let transaction = if self.start_transaction {
+            let mut ctx =
+                trace_headers.transaction_context(tx.as_deref().unwrap_or_default(), "http.server");
+            if let Some(sampler) = &self.traces_sampler {
+                // Honor the sampling decision of the upstream service
+                if ctx.sampled().is_none() {
+                    ctx.set_sampled(rand::random::<f32>() < sampler(&ctx));
+                }
+            }
+            let transaction = hub.start_transaction(ctx);
+            transaction.set_request(sentry_req.clone());
+            Some(transaction)
+        } else {
+            None
+        };
+        // The body is only attached to events, not to every transaction
+        if client.is_some() {
+            sentry_req.data =
+                body::peek_request_body(&mut request, self.max_request_body_size).await;
+        }
+        let breadcrumb = http_breadcrumb(&sentry_req);
+        hub.add_breadcrumb(breadcrumb.clone());
+        let trace_context = match &transaction {
+            Some(_) => None,
+            None => trace_headers.trace_context(),
+        };
+        hub.configure_scope(|scope| {
+            scope.set_transaction(tx.as_deref());
+            if let Some(transaction) = &transaction {
+                scope.set_span(Some(transaction.clone().into()));
+            }
+            if let Some(trace_context) = trace_context {
+                scope.set_context("trace", trace_context);
+            }
+            scope.add_event_processor(move |event| Some(process_event(event, &sentry_req)));
+        });
+        let start_session =
+            self.start_session && client.as_deref().is_some_and(session::is_tracked);
+        let request_hub = RequestHub::new(hub.clone(), breadcrumb, start_session);
+        request.set_ext(request_hub.clone());
+        if let Some(extractor) = &self.user_extractor {
+            if let Some(user) = extractor(&request) {
+                hub.configure_scope(|scope| scope.set_user(Some(user)));
+            }
+        }
+        if let Some(configurator) = &self.scope_configurator {
+            hub.configure_scope(|scope| configurator(&request, scope));
+        }
+        if start_session {
+            hub.start_session();
+        }
+
+        let future = inner(request).bind_hub(hub.clone());
+        #[cfg(feature = "surf")]
+        let future = BindIncomingTrace::new(future, trace_headers.into_incoming_trace());
+        let result = future.await;
+        // An error returned by an inner middleware ends the request like a response
+        let (status, breadcrumb) = match &result {
+            Ok(response) => (response.status(), request_hub.record_response(response)),
+            Err(error) => (error.status(), request_hub.record_status(error.status())),
+        };
+
+        let captured = request_hub.reported.lock().unwrap().is_some();
+        if self.client_error_breadcrumbs && !captured && status.is_client_error() {
+            parent_hub.add_breadcrumb(breadcrumb);
+        }
+
+        if let Some(transaction) = transaction {
+            transaction.set_status(map_status(status));
+            Hub::run(hub.clone(), || transaction.finish());
+        }
+        if start_session {
+            // Captured events already marked the session as errored or crashed, and the capture
+            // middleware ends it beforehand for the errors of responses
+            hub.end_session();
+        }
+
+        result
+    }
+}
+
+#[async_trait::async_trait]
+impl<State> tide::Middleware<State> for SentryScopeMiddleware<State>
+where
+    State: Clone + Send + Sync + 'static,
+{
+    async fn handle(&self, request: Request<State>, next: tide::Next<'_, State>) -> tide::Result {
+        self.run(
+            request,
+            |request| async move { Ok(next.run(request).await) },
+        )
+        .await
+    }
+}
+
+/// Build a Sentry request struct from the HTTP request
+fn sentry_request_from_http<State>(
+    request: &Request<State>,
+    with_pii: bool,
+    middleware: &SentryScopeMiddleware<State>,
+) -> (Option<String>, SentryRequest) {
+    // The route pattern is only known once the request is routed, see `SentryRouteMiddleware`
+    let transaction = Some(request.url().path().to_string());
+
+    let mut url = request.url().clone();
+    scrub::redact_query(&mut url, &middleware.sensitive_query_params);
+
+    let header_filter = &middleware.header_filter;
+    let mut sentry_req = SentryRequest {
+        query_string: url.query().map(String::from),
+        url: Some(url),
+        method: Some(request.method().to_string()),
+        headers: request
+            .iter()
+            .filter(|(k, _)| header_filter.is_allowed(k, with_pii))
+            .map(|(k, v)| {
+                // The `Display` of the values is list-formatted, join them like repeated headers
+                let values: Vec<_> = v.iter().map(|x| x.as_str()).collect();
+                (k.to_string(), values.join(", "))
+            })
+            .collect(),
+        ..Default::default()
+    };
+
+    // Cookies are only included if PII is enabled, just like the `Cookie` header
+    if header_filter.is_allowed(&COOKIE, with_pii) {
+        if let Some(cookies) = request.header(COOKIE) {
+            let cookies: Vec<_> = cookies.iter().map(|x| x.as_str()).collect();
+            sentry_req.cookies = Some(cookies.join("; "));
+        }
+    }
+
+    // If PII is enabled, include the remote address
+    if with_pii {
+        let remote = match &middleware.trusted_proxies {
+            Some(proxies) => proxies.client_ip(request).map(|x| x.to_string()),
+            None => request.remote().map(String::from),
+        };
+        if let Some(remote) = remote {
+            sentry_req.env.insert("REMOTE_ADDR".into(), remote);
+        }
+    };
+
+    (transaction, sentry_req)
+}
+
+#[cfg(test)]
+mod tests {
+    use sentry_core::protocol::{Context, EnvelopeItem, SpanStatus};
+    use sentry_core::test::TestTransport;
+    use sentry_core::{Client, ClientOptions};
+    use tide::http::{Method, Url};
+    use tide::StatusCode;
+
+    use super::*;
+
+    #[async_std::test]
+    async fn transaction_is_finished_when_the_request_fails() {
+        let transport = TestTransport::new();
+        let options = ClientOptions {
+            dsn: Some("https://public@sentry.invalid/1".parse().unwrap()),
+            transport: Some(Arc::new(transport.clone())),
+            traces_sample_rate: 1.0,
+            ..Default::default()
+        };
+        let hub = Hub::new(Some(Arc::new(Client::from(options))), Default::default());
+        let middleware = SentryScopeMiddleware::new().with_hub(Arc::new(hub));
+
+        let url = Url::parse("http://localhost/fail").unwrap();
+        let request: Request<()> = tide::http::Request::new(Method::Get, url).into();
+        let result = middleware
+            .run(request, |_| async {
+                Err(tide::Error::from_str(
+                    StatusCode::ServiceUnavailable,
+                    "down",
+                ))
+            })
+            .await;
+        assert_eq!(result.unwrap_err().status(), StatusCode::ServiceUnavailable);
+
+        let envelopes = transport.fetch_and_clear_envelopes();
+        let transaction = envelopes
+            .iter()
+            .flat_map(|envelope| envelope.items())
+            .find_map(|item| match item {
+                EnvelopeItem::Transaction(transaction) => Some(transaction),
+                _ => None,
+            })
+            .unwrap();
+        let status = match transaction.contexts.get("trace") {
+            Some(Context::Trace(trace)) => trace.status,
+            _ => None,
+        };
+        assert_eq!(status, Some(SpanStatus::Unavailable));
+    }
+}
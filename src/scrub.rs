@@ -0,0 +1,98 @@
+use percent_encoding::percent_decode_str;
+use tide::http::headers::HeaderName;
+use tide::http::Url;
+
+/// Substitute for the values of sensitive query parameters
+const FILTERED: &str = "[Filtered]";
+
+/// Headers which are never sent to Sentry unless PII is enabled
+const SENSITIVE_HEADERS: &[&str] = &[
+    "authorization",
+    "proxy-authorization",
+    "cookie",
+    "set-cookie",
+    "x-api-key",
+    "api-key",
+    "x-auth-token",
+    "x-csrf-token",
+    "x-xsrf-token",
+    "x-forwarded-for",
+    "x-real-ip",
+    "forwarded",
+];
+
+/// Decides which request headers are attached to Sentry events
+#[derive(Debug, Default, Clone)]
+pub(crate) struct HeaderFilter {
+    allowed: Vec<HeaderName>,
+    denied: Vec<HeaderName>,
+}
+
+impl HeaderFilter {
+    pub(crate) fn allow(&mut self, name: HeaderName) {
+        self.denied.retain(|x| *x != name);
+        self.allowed.push(name);
+    }
+
+    pub(crate) fn deny(&mut self, name: HeaderName) {
+        self.allowed.retain(|x| *x != name);
+        self.denied.push(name);
+    }
+
+    pub(crate) fn is_allowed(&self, name: &HeaderName, with_pii: bool) -> bool {
+        if self.denied.contains(name) {
+            false
+        } else if with_pii || self.allowed.contains(name) {
+            true
+        } else {
+            !SENSITIVE_HEADERS.contains(&name.as_str())
+        }
+    }
+}
+
+/// Query parameters whose values are not sent to Sentry, matched by substring
+pub(crate) const SENSITIVE_QUERY_PARAMS: &[&str] = &[
+    "token",
+    "password",
+    "passwd",
+    "secret",
+    "api_key",
+    "apikey",
+    "auth",
+    "session",
+    "signature",
+];
+
+/// Replace the values of sensitive query parameters in the URL
+///
+/// A parameter is sensitive if its name contains one of `sensitive_params`, ignoring case. Only
+/// the values of the sensitive parameters are replaced, the rest of the query is kept as it was
+/// received.
+pub(crate) fn redact_query<S: AsRef<str>>(url: &mut Url, sensitive_params: &[S]) {
+    let is_sensitive = |key: &str| {
+        let key = key.replace('+', " ");
+        let key = percent_decode_str(&key)
+            .decode_utf8_lossy()
+            .to_ascii_lowercase();
+        sensitive_params.iter().any(|x| key.contains(x.as_ref()))
+    };
+    let query = match url.query() {
+        Some(query) => query,
+        None => return,
+    };
+
+    let mut redacted = false;
+    let pairs: Vec<_> = query
+        .split('&')
+        .map(|pair| match pair.split_once('=') {
+            Some((key, _)) if is_sensitive(key) => {
+                redacted = true;
+                format!("{}={}", key, FILTERED)
+            }
+            _ => pair.to_string(),
+        })
+        .collect();
+    if redacted {
+        url.set_query(Some(&pairs.join("&")));
+    }
+}
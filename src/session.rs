@@ -0,0 +1,138 @@
+use std::sync::{Arc, OnceLock};
+
+use sentry_core::protocol::{Envelope, Event};
+use sentry_core::{Client, ClientOptions, Hub, Level, SessionMode, Transport};
+
+/// Whether the client tracks a session for each request
+pub(crate) fn is_tracked(client: &Client) -> bool {
+    let options = client.options();
+    options.auto_session_tracking && options.session_mode == SessionMode::Request
+}
+
+/// End the session of the request as errored, before an unhandled error is captured
+///
+/// sentry-core marks the session as crashed when an event with an unhandled exception is
+/// captured, while only panics crash a request. The error is counted on the session with an
+/// event which runs through the scope but is never sent, then the session is ended, so that the
+/// event of the error does not update it.
+///
+/// sentry-core offers no other way to count an error on a session: this relies on the client
+/// updating the session of the scope after the event processors and `before_send`, and before
+/// sampling the event, see the tests. The event processors of the scope therefore see the empty
+/// event, and if one of them drops it, the session is reported as exited instead.
+pub(crate) fn end_session_errored(hub: &Hub) {
+    let event = Event {
+        level: Level::Error,
+        ..Default::default()
+    };
+    hub.configure_scope(|scope| session_client().capture_event(event, Some(scope)));
+    hub.end_session();
+}
+
+/// A client which drops all events, to update sessions without sending events
+fn session_client() -> &'static Client {
+    static CLIENT: OnceLock<Client> = OnceLock::new();
+    CLIENT.get_or_init(|| {
+        Client::from(ClientOptions {
+            // Events are only processed if a DSN and a transport are configured
+            dsn: Some("https://public@sentry.invalid/0".parse().unwrap()),
+            transport: Some(Arc::new(|_: &ClientOptions| {
+                Arc::new(NoopTransport) as Arc<dyn Transport>
+            })),
+            sample_rate: 0.0,
+            session_mode: SessionMode::Request,
+            ..Default::default()
+        })
+    })
+}
+
+struct NoopTransport;
+
+impl Transport for NoopTransport {
+    fn send_envelope(&self, _: Envelope) {}
+}
+
+#[cfg(test)]
+mod tests {
+    use sentry_core::protocol::{EnvelopeItem, Exception, Mechanism};
+    use sentry_core::test::TestTransport;
+
+    use super::*;
+
+    /// The exited, errored and crashed sessions of a hub whose session ended after `f`
+    fn session_counts(f: impl FnOnce(&Hub)) -> (u32, u32, u32) {
+        let transport = TestTransport::new();
+        let client = Arc::new(Client::from(ClientOptions {
+            dsn: Some("https://public@sentry.invalid/1".parse().unwrap()),
+            transport: Some(Arc::new(transport.clone())),
+            release: Some("app@1.0.0".into()),
+            session_mode: SessionMode::Request,
+            ..Default::default()
+        }));
+        let hub = Hub::new(Some(client.clone()), Default::default());
+        hub.start_session();
+        f(&hub);
+        hub.end_session();
+        client.close(None);
+
+        let mut counts = (0, 0, 0);
+        for envelope in transport.fetch_and_clear_envelopes() {
+            for item in envelope.items() {
+                if let EnvelopeItem::SessionAggregates(aggregates) = item {
+                    for bucket in &aggregates.aggregates {
+                        counts.0 += bucket.exited;
+                        counts.1 += bucket.errored;
+                        counts.2 += bucket.crashed;
+                    }
+                }
+            }
+        }
+        counts
+    }
+
+    fn unhandled_error() -> Event<'static> {
+        Event {
+            exception: vec![Exception {
+                ty: "Error".into(),
+                mechanism: Some(Mechanism {
+                    ty: "tide".into(),
+                    handled: Some(false),
+                    ..Default::default()
+                }),
+                ..Default::default()
+            }]
+            .into(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn unhandled_errors_crash_sessions() {
+        let counts = session_counts(|hub| {
+            hub.capture_event(unhandled_error());
+        });
+        assert_eq!(counts, (0, 0, 1));
+    }
+
+    #[test]
+    fn unsampled_events_count_on_the_session() {
+        // Pins the behavior of sentry-core `end_session_errored` relies on
+        let counts = session_counts(|hub| {
+            let event = Event {
+                level: Level::Error,
+                ..Default::default()
+            };
+            hub.configure_scope(|scope| session_client().capture_event(event, Some(scope)));
+        });
+        assert_eq!(counts, (0, 1, 0));
+    }
+
+    #[test]
+    fn sessions_ended_errored_are_not_crashed() {
+        let counts = session_counts(|hub| {
+            end_session_errored(hub);
+            hub.capture_event(unhandled_error());
+        });
+        assert_eq!(counts, (0, 1, 0));
+    }
+}
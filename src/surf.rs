@@ -0,0 +1,189 @@
+//! Instrumentation of outgoing HTTP requests made with surf.
+
+use std::fmt;
+use std::sync::Arc;
+use std::time::Instant;
+
+use ::surf::middleware::{Middleware, Next};
+use ::surf::{Client, Request, Response};
+use percent_encoding::{utf8_percent_encode, NON_ALPHANUMERIC};
+use sentry_core::protocol::{Request as SentryRequest, SpanStatus};
+use sentry_core::{ClientOptions, Hub, Span};
+
+use crate::propagation::{
+    IncomingTrace, BAGGAGE_HEADER, BAGGAGE_SENTRY_PREFIX, SENTRY_TRACE_HEADER,
+};
+use crate::scrub;
+
+/// Surf middleware which records breadcrumbs and spans of outgoing HTTP requests, and propagates
+/// the trace to the called services.
+///
+/// Each request is recorded as an `http` breadcrumb, and a `http.client` span which is a child of
+/// the span of the scope, e.g. the transaction of the tide request started by
+/// [`SentryMiddleware`]. The `sentry-trace` and `baggage` headers are injected so that the called
+/// service continues the trace, with the dynamic sampling context of the incoming request if it
+/// continued a trace. Without a span, e.g. if transactions are disabled, the called service
+/// continues the trace of the incoming request.
+///
+/// By default, the current hub is used, which is the hub of the tide request when the client is
+/// used by an endpoint behind [`SentryMiddleware`].
+///
+/// [`SentryMiddleware`]: crate::SentryMiddleware
+#[derive(Default)]
+pub struct SentrySurfMiddleware {
+    hub: Option<Arc<Hub>>,
+}
+
+impl SentrySurfMiddleware {
+    pub fn new() -> Self {
+        Self { hub: None }
+    }
+
+    /// Reconfigures the middleware so that it uses a specific hub instead of the current one.
+    pub fn with_hub(mut self, hub: Arc<Hub>) -> Self {
+        self.hub = Some(hub);
+        self
+    }
+
+    /// Reconfigures the middleware so that it uses the current hub for every request.
+    pub fn with_current_hub(mut self) -> Self {
+        self.hub = None;
+        self
+    }
+}
+
+impl fmt::Debug for SentrySurfMiddleware {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SentrySurfMiddleware")
+            .field("hub", &self.hub)
+            .finish()
+    }
+}
+
+#[async_trait::async_trait]
+impl Middleware for SentrySurfMiddleware {
+    async fn handle(
+        &self,
+        mut request: Request,
+        client: Client,
+        next: Next<'_>,
+    ) -> ::surf::Result<Response> {
+        let started = Instant::now();
+        let hub = self.hub.clone().unwrap_or_else(Hub::current);
+
+        let mut url = request.url().clone();
+        scrub::redact_query(&mut url, scrub::SENSITIVE_QUERY_PARAMS);
+        let sentry_req = SentryRequest {
+            method: Some(request.method().to_string()),
+            url: Some(url.clone()),
+            ..Default::default()
+        };
+        let breadcrumb = crate::http_breadcrumb(&sentry_req);
+
+        let span = hub.configure_scope(|scope| scope.get_span()).map(|parent| {
+            let description = format!("{} {}", request.method(), url);
+            let span = parent.start_child("http.client", &description);
+            span.set_request(sentry_req);
+            span
+        });
+        let incoming = IncomingTrace::current();
+        if let Some(span) = &span {
+            for (name, value) in span.iter_headers() {
+                request.insert_header(name, value);
+            }
+            // Continue the dynamic sampling context of the head of the trace, if known
+            let incoming_dsc = incoming
+                .as_ref()
+                .filter(|x| x.trace_id == span.get_trace_context().trace_id)
+                .and_then(|x| x.dynamic_sampling_context.as_deref());
+            match (incoming_dsc, hub.client()) {
+                (Some(dsc), _) => inject_baggage(&mut request, dsc),
+                (None, Some(client)) => {
+                    let dsc = dynamic_sampling_context(span, client.options());
+                    inject_baggage(&mut request, &dsc);
+                }
+                (None, None) => {}
+            }
+        } else if let Some(incoming) = &incoming {
+            // Without a span, the called service continues the trace of the incoming request
+            request.insert_header(SENTRY_TRACE_HEADER, incoming.sentry_trace.as_str());
+            if let Some(dsc) = &incoming.dynamic_sampling_context {
+                inject_baggage(&mut request, dsc);
+            }
+        }
+
+        let result = next.run(request, client).await;
+
+        let status = match &result {
+            Ok(response) => {
+                let breadcrumb = crate::http_response_breadcrumb(
+                    breadcrumb,
+                    response.status(),
+                    started.elapsed(),
+                );
+                hub.add_breadcrumb(breadcrumb);
+                crate::map_status(response.status())
+            }
+            Err(_) => {
+                hub.add_breadcrumb(breadcrumb);
+                SpanStatus::UnknownError
+            }
+        };
+        if let Some(span) = span {
+            span.set_status(status);
+            span.finish();
+        }
+
+        result
+    }
+}
+
+/// Append the members of a dynamic sampling context to the `baggage` header of the request
+///
+/// If the request already carries a dynamic sampling context, it is left as is.
+fn inject_baggage(request: &mut Request, dsc: &str) {
+    let existing = request
+        .header(BAGGAGE_HEADER)
+        .map(|x| x.as_str().to_string());
+    if existing.as_deref().is_some_and(|x| {
+        x.split(',')
+            .any(|x| x.trim().starts_with(BAGGAGE_SENTRY_PREFIX))
+    }) {
+        return;
+    }
+
+    let baggage: Vec<_> = existing.as_deref().into_iter().chain([dsc]).collect();
+    request.insert_header(BAGGAGE_HEADER, baggage.join(","));
+}
+
+/// Build the dynamic sampling context of a trace started by this service, from the client options
+fn dynamic_sampling_context(span: &Span, options: &ClientOptions) -> String {
+    let trace_context = span.get_trace_context();
+    let mut dsc = vec![("trace_id", trace_context.trace_id.to_string())];
+    if let Some(dsn) = &options.dsn {
+        dsc.push(("public_key", dsn.public_key().to_string()));
+    }
+    if let Some(release) = &options.release {
+        dsc.push(("release", release.to_string()));
+    }
+    if let Some(environment) = &options.environment {
+        dsc.push(("environment", environment.to_string()));
+    }
+    if let Some((_, sentry_trace)) = span.iter_headers().next() {
+        // The sampling decision is the last part of `sentry-trace`, if any
+        match sentry_trace.rsplit('-').next() {
+            Some("1") => dsc.push(("sampled", "true".into())),
+            Some("0") => dsc.push(("sampled", "false".into())),
+            _ => {}
+        }
+    }
+
+    let members: Vec<_> = dsc
+        .iter()
+        .map(|(k, v)| {
+            let v = utf8_percent_encode(v, NON_ALPHANUMERIC);
+            format!("{}{}={}", BAGGAGE_SENTRY_PREFIX, k, v)
+        })
+        .collect();
+    members.join(",")
+}
@@ -0,0 +1,231 @@
+//! An HTTP transport for the Sentry client which runs on async-std.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+use async_std::channel::{self, Receiver, Sender, TrySendError};
+use async_std::{future, task};
+use sentry_core::protocol::{Envelope, EnvelopeItem};
+use sentry_core::{sentry_debug, ClientOptions, Transport, TransportFactory};
+use surf::{StatusCode, Url};
+
+/// How many envelopes are queued before new ones are dropped
+const QUEUE_SIZE: usize = 30;
+/// How long to back off if rate limited without being told for how long
+const DEFAULT_RETRY_AFTER: Duration = Duration::from_secs(60);
+
+/// Creates an [`AsyncStdTransport`] for each client, to be set as the `transport` of the client
+/// options.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct AsyncStdTransportFactory;
+
+impl TransportFactory for AsyncStdTransportFactory {
+    fn create_transport(&self, options: &ClientOptions) -> Arc<dyn Transport> {
+        Arc::new(AsyncStdTransport::new(options))
+    }
+}
+
+/// A transport which sends envelopes over HTTP from a background async-std task.
+///
+/// Envelopes are queued and sent one after the other, and dropped if the queue is full. The
+/// transport backs off as told by the `X-Sentry-Rate-Limits` and `Retry-After` headers of the
+/// responses, dropping the envelope items of rate limited categories meanwhile.
+///
+/// The envelopes are sent to the envelope endpoint of the DSN, which may be a plain HTTP server,
+/// e.g. a mock in tests.
+pub struct AsyncStdTransport {
+    sender: Sender<Task>,
+}
+
+enum Task {
+    SendEnvelope(Envelope),
+    Flush(Sender<()>),
+}
+
+impl AsyncStdTransport {
+    /// Creates a transport for the DSN of the given options, and spawns its background task.
+    pub fn new(options: &ClientOptions) -> Self {
+        let (sender, receiver) = channel::bounded(QUEUE_SIZE);
+        let endpoint = options.dsn.as_ref().and_then(|dsn| {
+            let url = Url::parse(dsn.envelope_api_url().as_str()).ok()?;
+            let auth = dsn.to_auth(Some(&options.user_agent)).to_string();
+            Some((url, auth))
+        });
+
+        let worker = Worker {
+            client: surf::Client::new(),
+            endpoint,
+            rate_limiter: RateLimiter::default(),
+        };
+        task::spawn(worker.run(receiver));
+
+        Self { sender }
+    }
+
+    /// Waits until the envelopes queued so far are sent, or the timeout elapses.
+    ///
+    /// Returns `false` on timeout. Unlike [`Transport::flush`], this does not block the thread,
+    /// so it can be awaited at the shutdown of a server.
+    pub async fn flush_async(&self, timeout: Duration) -> bool {
+        let flushed = async {
+            let (sender, receiver) = channel::bounded(1);
+            // The flush is queued behind the pending envelopes
+            if self.sender.send(Task::Flush(sender)).await.is_err() {
+                return false;
+            }
+            receiver.recv().await.is_ok()
+        };
+        future::timeout(timeout, flushed).await.unwrap_or(false)
+    }
+}
+
+impl fmt::Debug for AsyncStdTransport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AsyncStdTransport")
+            .field("queued", &self.sender.len())
+            .finish()
+    }
+}
+
+impl Transport for AsyncStdTransport {
+    fn send_envelope(&self, envelope: Envelope) {
+        if let Err(TrySendError::Full(_)) = self.sender.try_send(Task::SendEnvelope(envelope)) {
+            sentry_debug!("envelope dropped, the transport queue is full");
+        }
+    }
+
+    fn flush(&self, timeout: Duration) -> bool {
+        task::block_on(self.flush_async(timeout))
+    }
+
+    fn shutdown(&self, timeout: Duration) -> bool {
+        let flushed = self.flush(timeout);
+        // The background task stops once the queue is drained
+        self.sender.close();
+        flushed
+    }
+}
+
+/// The background task of the transport
+struct Worker {
+    client: surf::Client,
+    /// The envelope API URL and the `X-Sentry-Auth` header, if a DSN is configured
+    endpoint: Option<(Url, String)>,
+    rate_limiter: RateLimiter,
+}
+
+impl Worker {
+    async fn run(mut self, receiver: Receiver<Task>) {
+        while let Ok(task) = receiver.recv().await {
+            match task {
+                Task::SendEnvelope(envelope) => self.send(envelope).await,
+                Task::Flush(sender) => {
+                    let _ = sender.send(()).await;
+                }
+            }
+        }
+    }
+
+    async fn send(&mut self, envelope: Envelope) {
+        let (url, auth) = match &self.endpoint {
+            Some(endpoint) => endpoint,
+            None => return,
+        };
+        let envelope = match self.rate_limiter.filter_envelope(envelope) {
+            Some(envelope) => envelope,
+            None => return,
+        };
+        let mut body = Vec::new();
+        if envelope.to_writer(&mut body).is_err() {
+            return;
+        }
+
+        let request = surf::post(url.clone())
+            .header("X-Sentry-Auth", auth.as_str())
+            .content_type("application/x-sentry-envelope")
+            .body(body);
+        match self.client.send(request).await {
+            Ok(response) => self.rate_limiter.update(&response),
+            Err(error) => {
+                sentry_debug!("failed to send envelope: {}", error);
+            }
+        }
+    }
+}
+
+/// Tracks until when envelope items are rate limited, per category
+#[derive(Debug, Default)]
+struct RateLimiter {
+    global: Option<SystemTime>,
+    categories: HashMap<String, SystemTime>,
+}
+
+impl RateLimiter {
+    /// Update the rate limits from the headers of a response
+    fn update(&mut self, response: &surf::Response) {
+        let now = SystemTime::now();
+        if let Some(limits) = response.header("x-sentry-rate-limits") {
+            for limit in limits.iter().flat_map(|x| x.as_str().split(',')) {
+                // Limits look like `retry_after:categories:scope:reason`
+                let mut parts = limit.trim().split(':');
+                let retry_after = match parts.next().and_then(|x| x.parse::<f64>().ok()) {
+                    Some(secs) => retry_after(now, Some(secs)),
+                    None => continue,
+                };
+                let categories = parts.next().unwrap_or_default();
+                if categories.is_empty() {
+                    self.global = Some(retry_after);
+                }
+                for category in categories.split(';').filter(|x| !x.is_empty()) {
+                    self.categories.insert(category.to_string(), retry_after);
+                }
+            }
+        } else if response.status() == StatusCode::TooManyRequests {
+            // `Retry-After` only means a rate limit on a 429, e.g. not on a 503
+            let secs = response
+                .header("retry-after")
+                .and_then(|x| x.as_str().trim().parse::<f64>().ok());
+            self.global = Some(retry_after(now, secs));
+        }
+    }
+
+    fn is_limited(&self, category: Option<&str>) -> bool {
+        let now = SystemTime::now();
+        let is_active = |until: &SystemTime| *until > now;
+        self.global.as_ref().is_some_and(is_active)
+            || category
+                .and_then(|x| self.categories.get(x))
+                .is_some_and(is_active)
+    }
+
+    /// Drop the items of rate limited categories from the envelope
+    ///
+    /// Returns `None` if no item is left.
+    fn filter_envelope(&self, envelope: Envelope) -> Option<Envelope> {
+        envelope.filter(|item| !self.is_limited(category(item)))
+    }
+}
+
+/// Until when to back off, given a number of seconds from a response
+///
+/// Falls back to [`DEFAULT_RETRY_AFTER`] if there is none or it is unusable, e.g. `inf` or too
+/// large to be added to `now`.
+fn retry_after(now: SystemTime, secs: Option<f64>) -> SystemTime {
+    secs.and_then(|x| Duration::try_from_secs_f64(x.max(0.0)).ok())
+        .and_then(|x| now.checked_add(x))
+        .unwrap_or(now + DEFAULT_RETRY_AFTER)
+}
+
+/// The rate limiting category of an envelope item
+fn category(item: &EnvelopeItem) -> Option<&'static str> {
+    match item {
+        EnvelopeItem::Event(_) => Some("error"),
+        EnvelopeItem::Transaction(_) => Some("transaction"),
+        EnvelopeItem::SessionUpdate(_) | EnvelopeItem::SessionAggregates(_) => Some("session"),
+        EnvelopeItem::Attachment(_) => Some("attachment"),
+        EnvelopeItem::Profile(_) => Some("profile"),
+        _ => None,
+    }
+}
@@ -0,0 +1,131 @@
+mod common;
+
+use std::sync::Arc;
+
+use common::{hub_with_options, send, url};
+use sentry_core::protocol::EnvelopeItem;
+use sentry_core::test::TestTransport;
+use sentry_core::ClientOptions;
+use sentry_tide::{MaxRequestBodySize, SentryMiddleware};
+use tide::http::headers::CONTENT_TYPE;
+use tide::http::{Method, Request};
+use tide::StatusCode;
+
+/// An app whose endpoint fails with the content type and the body it received
+fn app(max_size: MaxRequestBodySize) -> (tide::Server<()>, Arc<TestTransport>) {
+    let (hub, transport) = hub_with_options(ClientOptions {
+        traces_sample_rate: 1.0,
+        ..Default::default()
+    });
+    let mut app = tide::new();
+    app.with(
+        SentryMiddleware::new()
+            .with_hub(hub)
+            .max_request_body_size(max_size),
+    );
+    app.at("/")
+        .post(|mut request: tide::Request<()>| async move {
+            let content_type = request.header(CONTENT_TYPE).map(|x| x.as_str().to_string());
+            let body = request.body_string().await?;
+            Err::<String, _>(tide::Error::from_str(
+                StatusCode::InternalServerError,
+                format!("{:?} {}", content_type, body),
+            ))
+        });
+    (app, transport)
+}
+
+async fn post(app: &tide::Server<()>, content_type: Option<&str>, body: &str) {
+    let mut request = Request::new(Method::Post, url("/"));
+    request.set_body(body);
+    request.remove_header(CONTENT_TYPE);
+    if let Some(content_type) = content_type {
+        request.insert_header(CONTENT_TYPE, content_type);
+    }
+    send(app, request).await;
+}
+
+#[async_std::test]
+async fn body_is_attached_and_passed_on_unchanged() {
+    let (app, transport) = app(MaxRequestBodySize::Small);
+
+    post(&app, Some("application/json"), r#"{"a":1}"#).await;
+    post(&app, None, "plain").await;
+
+    let events = transport.fetch_and_clear_events();
+    let received: Vec<_> = events
+        .iter()
+        .map(|x| x.exception.values[0].value.clone().unwrap())
+        .collect();
+    assert_eq!(
+        received,
+        [r#"Some("application/json") {"a":1}"#, "None plain"]
+    );
+    let data: Vec<_> = events
+        .iter()
+        .map(|x| x.request.as_ref().unwrap().data.clone())
+        .collect();
+    assert_eq!(data, [Some(r#"{"a":1}"#.into()), Some("plain".into())]);
+}
+
+#[async_std::test]
+async fn body_is_truncated() {
+    let (app, transport) = app(MaxRequestBodySize::Small);
+    let body = "x".repeat(1500);
+
+    post(&app, None, &body).await;
+
+    let events = transport.fetch_and_clear_events();
+    let received = events[0].exception.values[0].value.as_deref().unwrap();
+    assert_eq!(received, format!("None {}", body));
+    let data = events[0].request.as_ref().unwrap().data.as_deref().unwrap();
+    assert_eq!(data, format!("{}[truncated]", &body[..1000]));
+}
+
+#[async_std::test]
+async fn body_is_untouched_if_not_attached() {
+    let (app, transport) = app(MaxRequestBodySize::None);
+
+    post(&app, None, "plain").await;
+
+    let events = transport.fetch_and_clear_events();
+    assert_eq!(
+        events[0].exception.values[0].value.as_deref(),
+        Some("None plain")
+    );
+    assert_eq!(events[0].request.as_ref().unwrap().data, None);
+}
+
+#[async_std::test]
+async fn body_is_truncated_at_a_char_boundary() {
+    let (app, transport) = app(MaxRequestBodySize::Small);
+    let body = format!("x{}", "é".repeat(600));
+
+    post(&app, None, &body).await;
+
+    let events = transport.fetch_and_clear_events();
+    let data = events[0].request.as_ref().unwrap().data.as_deref().unwrap();
+    assert_eq!(data, format!("x{}[truncated]", "é".repeat(499)));
+}
+
+#[async_std::test]
+async fn body_is_not_attached_to_transactions() {
+    let (app, transport) = app(MaxRequestBodySize::Small);
+
+    post(&app, None, "plain").await;
+
+    let mut data = Vec::new();
+    for envelope in transport.fetch_and_clear_envelopes() {
+        for item in envelope.items() {
+            match item {
+                EnvelopeItem::Event(event) => data.push(event.request.clone().unwrap().data),
+                EnvelopeItem::Transaction(transaction) => {
+                    data.push(transaction.request.clone().unwrap().data)
+                }
+                _ => {}
+            }
+        }
+    }
+    data.sort();
+    assert_eq!(data, [None, Some("plain".into())]);
+}
@@ -0,0 +1,205 @@
+mod common;
+
+use std::sync::Arc;
+
+use common::{get, hub_with_transport};
+use sentry_core::test::TestTransport;
+use sentry_core::Level;
+use sentry_tide::{CapturePolicy, SentryMiddleware, SentryRequestExt};
+use tide::{Response, StatusCode};
+
+/// An app which responds with the status of the path, without an error
+fn app(policy: CapturePolicy) -> (tide::Server<()>, Arc<TestTransport>) {
+    let (hub, transport) = hub_with_transport();
+    let mut app = tide::new();
+    app.with(
+        SentryMiddleware::new()
+            .with_hub(hub)
+            .capture_policy(policy)
+            .capture_error_responses(true),
+    );
+    app.at("/:status")
+        .get(|request: tide::Request<()>| async move {
+            let status: u16 = request.param("status")?.parse()?;
+            Ok(Response::new(status))
+        });
+    (app, transport)
+}
+
+#[async_std::test]
+async fn error_responses_are_captured() {
+    let (app, transport) = app(CapturePolicy::default());
+
+    get(&app, "/502").await;
+    get(&app, "/404").await;
+
+    let events = transport.fetch_and_clear_events();
+    assert_eq!(events.len(), 1);
+    assert_eq!(events[0].message.as_deref(), Some("502 Bad Gateway"));
+    assert_eq!(events[0].level, Level::Error);
+}
+
+#[async_std::test]
+async fn error_responses_follow_the_capture_policy() {
+    let policy = CapturePolicy::none()
+        .capture(503..504, Level::Warning)
+        .capture(500..600, Level::Error)
+        .ignore_status(StatusCode::BadGateway);
+    let (app, transport) = app(policy);
+
+    get(&app, "/502").await;
+    get(&app, "/503").await;
+    get(&app, "/504").await;
+
+    let events: Vec<_> = transport
+        .fetch_and_clear_events()
+        .into_iter()
+        .map(|x| (x.message.unwrap(), x.level))
+        .collect();
+    assert_eq!(
+        events,
+        [
+            ("503 Service Unavailable".into(), Level::Warning),
+            ("504 Gateway Timeout".into(), Level::Error),
+        ]
+    );
+}
+
+#[async_std::test]
+async fn error_responses_are_not_captured_without_a_range() {
+    let (app, transport) = app(CapturePolicy::none());
+
+    get(&app, "/500").await;
+
+    assert!(transport.fetch_and_clear_events().is_empty());
+}
+
+#[async_std::test]
+async fn errors_reported_by_handlers_are_captured_once() {
+    let (hub, transport) = hub_with_transport();
+    let mut app = tide::new();
+    app.with(SentryMiddleware::new().with_hub(hub).emit_header(true));
+    app.at("/").get(|request: tide::Request<()>| async move {
+        let error = std::io::Error::other("reported");
+        request.capture_sentry_error(&error);
+        Err::<String, _>(tide::Error::new(StatusCode::InternalServerError, error))
+    });
+
+    let response = get(&app, "/").await;
+    assert_eq!(response.status(), StatusCode::InternalServerError);
+
+    let events = transport.fetch_and_clear_events();
+    assert_eq!(events.len(), 1);
+    assert_eq!(
+        events[0].exception.values[0].value.as_deref(),
+        Some("reported")
+    );
+    let header = response.header("x-sentry-event").unwrap();
+    assert_eq!(header.as_str(), events[0].event_id.as_simple().to_string());
+}
+
+#[async_std::test]
+async fn errors_marked_as_reported_are_not_captured() {
+    let (hub, transport) = hub_with_transport();
+    let mut app = tide::new();
+    app.with(SentryMiddleware::new().with_hub(hub).emit_header(true));
+    app.at("/").get(|request: tide::Request<()>| async move {
+        let event_id = request
+            .sentry_hub()
+            .capture_message("reported", Level::Warning);
+        request.mark_sentry_reported(event_id);
+        Err::<String, _>(tide::Error::from_str(
+            StatusCode::InternalServerError,
+            "boom",
+        ))
+    });
+
+    let response = get(&app, "/").await;
+
+    let events = transport.fetch_and_clear_events();
+    assert_eq!(events.len(), 1);
+    assert_eq!(events[0].message.as_deref(), Some("reported"));
+    let header = response.header("x-sentry-event").unwrap();
+    assert_eq!(header.as_str(), events[0].event_id.as_simple().to_string());
+}
+
+#[derive(Debug)]
+struct Expected;
+
+impl std::fmt::Display for Expected {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("expected")
+    }
+}
+
+impl std::error::Error for Expected {}
+
+/// An app whose endpoints fail with errors of different types
+fn failing_app(middleware: SentryMiddleware<()>) -> tide::Server<()> {
+    let mut app = tide::new();
+    app.with(middleware);
+    app.at("/expected").get(|_| async {
+        Err::<String, _>(tide::Error::new(StatusCode::InternalServerError, Expected))
+    });
+    app.at("/io").get(|_| async {
+        let error = std::io::Error::other("io");
+        Err::<String, _>(tide::Error::new(StatusCode::InternalServerError, error))
+    });
+    app.at("/teapot").get(|_| async {
+        Err::<String, _>(tide::Error::from_str(
+            StatusCode::InternalServerError,
+            "teapot",
+        ))
+    });
+    app
+}
+
+async fn captured_errors(middleware: SentryMiddleware<()>) -> Vec<String> {
+    let (hub, transport) = hub_with_transport();
+    let app = failing_app(middleware.with_hub(hub));
+
+    for path in ["/expected", "/io", "/teapot"] {
+        get(&app, path).await;
+    }
+
+    transport
+        .fetch_and_clear_events()
+        .into_iter()
+        .map(|x| x.exception.values[0].value.clone().unwrap())
+        .collect()
+}
+
+#[async_std::test]
+async fn errors_are_ignored_by_type() {
+    let policy = CapturePolicy::default().ignore_error::<Expected>();
+
+    let errors = captured_errors(SentryMiddleware::new().capture_policy(policy)).await;
+
+    assert_eq!(errors, ["io", "teapot"]);
+}
+
+#[async_std::test]
+async fn errors_are_ignored_by_predicate() {
+    let policy = CapturePolicy::default()
+        .ignore_error_if(|error| error.downcast_ref::<std::io::Error>().is_some())
+        .ignore_error_if(|error| error.to_string() == "teapot");
+
+    let errors = captured_errors(SentryMiddleware::new().capture_policy(policy)).await;
+
+    assert_eq!(errors, ["expected"]);
+}
+
+#[async_std::test]
+async fn capture_server_errors_keeps_the_policy() {
+    let policy = CapturePolicy::default().ignore_error::<Expected>();
+
+    let middleware = SentryMiddleware::new()
+        .capture_policy(policy.clone())
+        .capture_server_errors(true);
+    assert_eq!(captured_errors(middleware).await, ["io", "teapot"]);
+
+    let middleware = SentryMiddleware::new()
+        .capture_server_errors(false)
+        .capture_policy(policy);
+    assert!(captured_errors(middleware).await.is_empty());
+}
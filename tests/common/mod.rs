@@ -0,0 +1,76 @@
+//! Helpers shared by the integration tests
+#![allow(dead_code)]
+
+use std::sync::Arc;
+
+use sentry_core::protocol::{Context, EnvelopeItem, SpanStatus, Transaction};
+use sentry_core::test::TestTransport;
+use sentry_core::{Client, ClientOptions, Hub};
+use tide::http::{Method, Request, Response, Url};
+use tide::StatusCode;
+
+/// A client with the given options, which records what it sends
+pub fn client_with_options(options: ClientOptions) -> (Arc<Client>, Arc<TestTransport>) {
+    let transport = TestTransport::new();
+    let options = ClientOptions {
+        dsn: Some("https://public@sentry.invalid/1".parse().unwrap()),
+        transport: Some(Arc::new(transport.clone())),
+        ..options
+    };
+    (Arc::new(Client::from(options)), transport)
+}
+
+/// A hub bound to a client with the given options, which records what it sends
+pub fn hub_with_options(options: ClientOptions) -> (Arc<Hub>, Arc<TestTransport>) {
+    let (client, transport) = client_with_options(options);
+    (
+        Arc::new(Hub::new(Some(client), Default::default())),
+        transport,
+    )
+}
+
+/// A hub bound to a client with the default options, which records what it sends
+pub fn hub_with_transport() -> (Arc<Hub>, Arc<TestTransport>) {
+    hub_with_options(Default::default())
+}
+
+pub fn url(path: &str) -> Url {
+    Url::parse("http://localhost").unwrap().join(path).unwrap()
+}
+
+pub async fn send(app: &tide::Server<()>, request: Request) -> Response {
+    app.respond(request).await.unwrap()
+}
+
+pub async fn get(app: &tide::Server<()>, path: &str) -> Response {
+    send(app, Request::new(Method::Get, url(path))).await
+}
+
+/// An endpoint which fails with an internal server error
+pub async fn boom(_: tide::Request<()>) -> tide::Result<String> {
+    Err(tide::Error::from_str(
+        StatusCode::InternalServerError,
+        "boom",
+    ))
+}
+
+/// The transactions sent through the transport
+pub fn transactions(transport: &TestTransport) -> Vec<Transaction<'static>> {
+    let mut transactions = Vec::new();
+    for envelope in transport.fetch_and_clear_envelopes() {
+        for item in envelope.items() {
+            if let EnvelopeItem::Transaction(transaction) = item {
+                transactions.push(transaction.clone());
+            }
+        }
+    }
+    transactions
+}
+
+/// The status of the trace context of a transaction
+pub fn status(transaction: &Transaction) -> Option<SpanStatus> {
+    match transaction.contexts.get("trace") {
+        Some(Context::Trace(trace)) => trace.status,
+        _ => None,
+    }
+}
@@ -0,0 +1,90 @@
+mod common;
+
+use common::{boom, get, hub_with_transport, send, url};
+use sentry_core::Hub;
+use sentry_tide::SentryMiddleware;
+use tide::http::{Method, Request};
+use tide::StatusCode;
+
+fn app(middleware: SentryMiddleware<()>) -> tide::Server<()> {
+    let mut app = tide::new();
+    app.with(middleware);
+    app.at("/error").get(boom);
+    app
+}
+
+#[async_std::test]
+async fn with_hub_captures_on_its_own_client() {
+    let (main_hub, main_transport) = hub_with_transport();
+    Hub::main().bind_client(main_hub.client());
+    let (hub, transport) = hub_with_transport();
+    let app = app(SentryMiddleware::new().with_hub(hub));
+
+    let response = get(&app, "/error").await;
+    assert_eq!(response.status(), StatusCode::InternalServerError);
+
+    let events = transport.fetch_and_clear_events();
+    assert_eq!(events.len(), 1);
+    assert_eq!(events[0].exception.values[0].value.as_deref(), Some("boom"));
+    assert!(main_transport.fetch_and_clear_events().is_empty());
+}
+
+#[async_std::test]
+async fn hubs_of_two_apps_are_separate() {
+    let (first_hub, first_transport) = hub_with_transport();
+    let (second_hub, second_transport) = hub_with_transport();
+    let first = app(SentryMiddleware::new().with_hub(first_hub));
+    let second = app(SentryMiddleware::new().with_hub(second_hub));
+
+    get(&first, "/error").await;
+    get(&first, "/error").await;
+    get(&second, "/error").await;
+
+    assert_eq!(first_transport.fetch_and_clear_events().len(), 2);
+    assert_eq!(second_transport.fetch_and_clear_events().len(), 1);
+}
+
+#[async_std::test]
+async fn request_scope_does_not_leak_into_the_hub() {
+    let (hub, transport) = hub_with_transport();
+    let app = app(SentryMiddleware::new().with_hub(hub.clone()));
+
+    get(&app, "/error").await;
+    hub.capture_message("after", sentry_core::Level::Info);
+
+    let events = transport.fetch_and_clear_events();
+    assert_eq!(events.len(), 2);
+    assert_eq!(events[0].transaction.as_deref(), Some("/error"));
+    assert_eq!(events[1].transaction, None);
+    assert!(events[1].request.is_none());
+}
+
+#[async_std::test]
+async fn hub_selector_picks_the_hub_of_each_request() {
+    let (tenant_a, transport_a) = hub_with_transport();
+    let (tenant_b, transport_b) = hub_with_transport();
+    let app = app(
+        SentryMiddleware::new().with_hub_selector(move |request: &tide::Request<()>| match request
+            .host()
+        {
+            Some("b.example.com") => tenant_b.clone(),
+            _ => tenant_a.clone(),
+        }),
+    );
+
+    for host in ["a.example.com", "b.example.com", "b.example.com"] {
+        let mut request = Request::new(Method::Get, url("/error"));
+        request.insert_header("Host", host);
+        send(&app, request).await;
+    }
+
+    let events_a = transport_a.fetch_and_clear_events();
+    let events_b = transport_b.fetch_and_clear_events();
+    assert_eq!(events_a.len(), 1);
+    assert_eq!(events_b.len(), 2);
+    let host = |event: &sentry_core::protocol::Event| {
+        event.request.as_ref().unwrap().headers.get("host").cloned()
+    };
+    assert_eq!(host(&events_a[0]).as_deref(), Some("a.example.com"));
+    assert_eq!(host(&events_b[0]).as_deref(), Some("b.example.com"));
+}
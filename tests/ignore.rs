@@ -0,0 +1,67 @@
+mod common;
+
+use std::sync::Arc;
+
+use common::{boom, get, hub_with_options, send, transactions, url};
+use sentry_core::{ClientOptions, Hub};
+use sentry_tide::{PathMatcher, SentryMiddleware, SentryRequestExt};
+use tide::http::{Method, Request};
+
+/// An app which tells at `/hub` whether it got a request hub, and fails elsewhere
+fn app(middleware: SentryMiddleware<()>) -> tide::Server<()> {
+    // Requests are handled on the thread of the test
+    let thread_hub = Hub::current();
+    let mut app = tide::new();
+    app.with(middleware);
+    app.at("/hub").all(move |request: tide::Request<()>| {
+        let is_thread_hub = Arc::ptr_eq(&request.sentry_hub(), &thread_hub);
+        async move { Ok(if is_thread_hub { "thread" } else { "request" }) }
+    });
+    app.at("/*").all(boom);
+    app
+}
+
+async fn hub_kind(app: &tide::Server<()>, method: Method) -> String {
+    let mut response = send(app, Request::new(method, url("/hub"))).await;
+    response.body_string().await.unwrap()
+}
+
+#[async_std::test]
+async fn ignored_methods_skip_the_hub_transaction_and_capture() {
+    let (hub, transport) = hub_with_options(ClientOptions {
+        traces_sample_rate: 1.0,
+        ..Default::default()
+    });
+    let app = app(SentryMiddleware::new()
+        .with_hub(hub.clone())
+        .ignore_methods([Method::Options]));
+
+    send(&app, Request::new(Method::Options, url("/users"))).await;
+    assert!(transport.fetch_and_clear_envelopes().is_empty());
+    assert_eq!(hub_kind(&app, Method::Options).await, "thread");
+
+    send(&app, Request::new(Method::Get, url("/users"))).await;
+    assert_eq!(transport.fetch_and_clear_events().len(), 1);
+    assert_eq!(hub_kind(&app, Method::Get).await, "request");
+}
+
+#[async_std::test]
+async fn ignored_paths_skip_the_hub_transaction_and_capture() {
+    let (hub, transport) = hub_with_options(ClientOptions {
+        traces_sample_rate: 1.0,
+        ..Default::default()
+    });
+    let app = app(SentryMiddleware::new().with_hub(hub).ignore_paths([
+        PathMatcher::exact("/health"),
+        PathMatcher::prefix("/static/"),
+        PathMatcher::glob("/assets/**"),
+    ]));
+
+    for path in ["/health", "/static/app.js", "/assets/css/app.css"] {
+        get(&app, path).await;
+    }
+    assert!(transport.fetch_and_clear_envelopes().is_empty());
+
+    get(&app, "/healthz").await;
+    assert_eq!(transactions(&transport).len(), 1);
+}
@@ -0,0 +1,63 @@
+//! Shutdown on a signal, in a separate test binary since the signal is sent to the process
+#![cfg(feature = "listen")]
+
+mod common;
+
+use std::process::Command;
+use std::time::Duration;
+
+use async_std::io::prelude::*;
+use async_std::net::TcpStream;
+use async_std::task;
+use common::hub_with_transport;
+use sentry_tide::{listen_with_flush, InFlightRequests, SentryMiddleware};
+use tide::StatusCode;
+
+#[async_std::test]
+async fn in_flight_requests_are_captured_before_the_clients_are_closed() {
+    let (hub, transport) = hub_with_transport();
+    let in_flight = InFlightRequests::new();
+    let mut app = tide::new();
+    app.with(in_flight.clone());
+    app.with(SentryMiddleware::new().with_hub(hub.clone()));
+    app.at("/slow").get(|_| async {
+        task::sleep(Duration::from_millis(300)).await;
+        Err::<String, _>(tide::Error::from_str(
+            StatusCode::InternalServerError,
+            "slow",
+        ))
+    });
+
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let server = task::spawn(listen_with_flush(
+        app,
+        listener,
+        in_flight.clone(),
+        [hub.clone(), hub.clone()],
+        Duration::from_secs(5),
+    ));
+
+    let mut stream = TcpStream::connect(addr).await.unwrap();
+    stream
+        .write_all(b"GET /slow HTTP/1.1\r\nhost: localhost\r\ncontent-length: 0\r\n\r\n")
+        .await
+        .unwrap();
+    while in_flight.count() == 0 {
+        task::sleep(Duration::from_millis(10)).await;
+    }
+
+    let status = Command::new("kill")
+        .args(["-TERM", &std::process::id().to_string()])
+        .status()
+        .unwrap();
+    assert!(status.success());
+    server.await.unwrap();
+
+    // The event of the request was captured before its client was closed
+    assert_eq!(in_flight.count(), 0);
+    let events = transport.fetch_and_clear_events();
+    assert_eq!(events.len(), 1);
+    assert_eq!(events[0].exception.values[0].value.as_deref(), Some("slow"));
+    assert!(!hub.client().unwrap().is_enabled());
+}
@@ -0,0 +1,68 @@
+mod common;
+
+use std::panic::AssertUnwindSafe;
+
+use common::{get, hub_with_transport};
+use futures_lite::FutureExt;
+use sentry_core::Level;
+use sentry_tide::SentryMiddleware;
+use tide::StatusCode;
+
+fn app(middleware: SentryMiddleware<()>) -> tide::Server<()> {
+    let mut app = tide::new();
+    app.with(middleware);
+    app.at("/panic").get(|_| async {
+        if true {
+            panic!("boom");
+        }
+        Ok("ok")
+    });
+    app
+}
+
+#[async_std::test]
+async fn panics_are_captured() {
+    let (hub, transport) = hub_with_transport();
+    let middleware = SentryMiddleware::new()
+        .with_hub(hub)
+        .capture_panics(true)
+        .emit_header(true);
+    let app = app(middleware);
+
+    let response = get(&app, "/panic").await;
+    assert_eq!(response.status(), StatusCode::InternalServerError);
+
+    let events = transport.fetch_and_clear_events();
+    assert_eq!(events.len(), 1);
+    let event = &events[0];
+    assert_eq!(event.level, Level::Fatal);
+    let exception = &event.exception.values[0];
+    assert_eq!(exception.ty, "panic");
+    assert_eq!(exception.value.as_deref(), Some("boom"));
+    let mechanism = exception.mechanism.as_ref().unwrap();
+    assert_eq!(mechanism.ty, "panic");
+    assert_eq!(mechanism.handled, Some(false));
+
+    // The frames of the panic machinery are trimmed
+    let frames = &exception.stacktrace.as_ref().unwrap().frames;
+    let innermost = frames.last().unwrap().function.as_deref().unwrap();
+    assert!(!innermost.contains("panicking"), "{}", innermost);
+
+    let header = response.header("x-sentry-event").unwrap();
+    assert_eq!(header.as_str(), event.event_id.as_simple().to_string());
+}
+
+#[async_std::test]
+async fn panics_unwind_if_not_captured() {
+    let (hub, transport) = hub_with_transport();
+    let middleware = SentryMiddleware::new()
+        .with_hub(hub)
+        .capture_panics(true)
+        .capture_panics(false);
+    let app = app(middleware);
+
+    let result = AssertUnwindSafe(get(&app, "/panic")).catch_unwind().await;
+
+    assert!(result.is_err());
+    assert!(transport.fetch_and_clear_events().is_empty());
+}
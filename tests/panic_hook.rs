@@ -0,0 +1,63 @@
+//! Panics with another panic hook which reports them, in a separate test binary since panic hooks
+//! are global
+
+mod common;
+
+use std::panic;
+
+use common::{get, hub_with_transport};
+use sentry_core::protocol::{Event, Exception, Mechanism};
+use sentry_core::Hub;
+use sentry_tide::SentryMiddleware;
+use tide::StatusCode;
+
+/// Install a panic hook which captures panics on the current hub, like the panic integration of
+/// the `sentry` crate
+fn install_reporting_hook() {
+    let next = panic::take_hook();
+    panic::set_hook(Box::new(move |info| {
+        let event = Event {
+            exception: vec![Exception {
+                ty: "panic".into(),
+                value: Some(info.to_string()),
+                mechanism: Some(Mechanism {
+                    ty: "panic".into(),
+                    handled: Some(false),
+                    ..Default::default()
+                }),
+                ..Default::default()
+            }]
+            .into(),
+            ..Default::default()
+        };
+        Hub::current().capture_event(event);
+        next(info);
+    }));
+}
+
+#[async_std::test]
+async fn panics_reported_by_another_hook_are_captured_once() {
+    install_reporting_hook();
+    let (hub, transport) = hub_with_transport();
+    let mut app = tide::new();
+    app.with(
+        SentryMiddleware::new()
+            .with_hub(hub)
+            .capture_panics(true)
+            .emit_header(true),
+    );
+    app.at("/panic").get(|_| async {
+        if true {
+            panic!("boom");
+        }
+        Ok("ok")
+    });
+
+    let response = get(&app, "/panic").await;
+    assert_eq!(response.status(), StatusCode::InternalServerError);
+
+    let events = transport.fetch_and_clear_events();
+    assert_eq!(events.len(), 1);
+    let header = response.header("x-sentry-event").unwrap();
+    assert_eq!(header.as_str(), events[0].event_id.as_simple().to_string());
+}
@@ -0,0 +1,89 @@
+mod common;
+
+use common::{boom, hub_with_options, send, url};
+use sentry_core::ClientOptions;
+use sentry_tide::{ForwardedHeader, SentryMiddleware, TrustedProxies};
+use tide::http::{Method, Request};
+
+/// Resolve the client address of a request with the given headers, sent by `10.0.0.1`
+async fn client_ip(proxies: TrustedProxies, headers: &[(&str, &str)]) -> Option<String> {
+    let (hub, transport) = hub_with_options(ClientOptions {
+        send_default_pii: true,
+        ..Default::default()
+    });
+    let mut app = tide::new();
+    app.with(
+        SentryMiddleware::new()
+            .with_hub(hub)
+            .trusted_proxies(proxies),
+    );
+    app.at("/").get(boom);
+
+    let mut request = Request::new(Method::Get, url("/"));
+    request.set_peer_addr(Some("10.0.0.1:4000"));
+    for (name, value) in headers {
+        request.append_header(*name, *value);
+    }
+    send(&app, request).await;
+
+    let events = transport.fetch_and_clear_events();
+    events[0].request.as_ref()?.env.get("REMOTE_ADDR").cloned()
+}
+
+#[async_std::test]
+async fn hops_are_read_from_x_forwarded_for_only() {
+    let headers = [
+        ("Forwarded", "for=6.6.6.6"),
+        ("X-Forwarded-For", "7.7.7.7, 1.2.3.4"),
+    ];
+
+    let ip = client_ip(TrustedProxies::hops(1), &headers).await;
+    assert_eq!(ip.as_deref(), Some("1.2.3.4"));
+    let ip = client_ip(TrustedProxies::hops(0), &headers).await;
+    assert_eq!(ip.as_deref(), Some("10.0.0.1"));
+}
+
+#[async_std::test]
+async fn hops_are_read_from_forwarded_only() {
+    let headers = [
+        ("Forwarded", "for=7.7.7.7, for=1.2.3.4"),
+        ("X-Forwarded-For", "6.6.6.6"),
+    ];
+    let proxies = TrustedProxies::hops(1).header(ForwardedHeader::Forwarded);
+
+    let ip = client_ip(proxies, &headers).await;
+    assert_eq!(ip.as_deref(), Some("1.2.3.4"));
+}
+
+#[async_std::test]
+async fn hops_span_repeated_headers() {
+    let headers = [
+        ("X-Forwarded-For", "7.7.7.7"),
+        ("X-Forwarded-For", "1.2.3.4"),
+    ];
+
+    let ip = client_ip(TrustedProxies::hops(1), &headers).await;
+    assert_eq!(ip.as_deref(), Some("1.2.3.4"));
+}
+
+#[async_std::test]
+async fn networks_skip_trusted_hops() {
+    let networks = vec!["10.0.0.0/8".parse().unwrap()];
+    let headers = [
+        ("Forwarded", "for=6.6.6.6"),
+        ("X-Forwarded-For", "7.7.7.7, 1.2.3.4, 10.1.1.1"),
+    ];
+
+    let ip = client_ip(TrustedProxies::networks(networks), &headers).await;
+    assert_eq!(ip.as_deref(), Some("1.2.3.4"));
+}
+
+#[async_std::test]
+async fn hops_beyond_the_chain_give_the_first_hop() {
+    let headers = [("X-Forwarded-For", "7.7.7.7, 1.2.3.4")];
+
+    let ip = client_ip(TrustedProxies::hops(5), &headers).await;
+    assert_eq!(ip.as_deref(), Some("7.7.7.7"));
+    let ip = client_ip(TrustedProxies::hops(usize::MAX), &headers).await;
+    assert_eq!(ip.as_deref(), Some("7.7.7.7"));
+}
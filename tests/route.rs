@@ -0,0 +1,49 @@
+mod common;
+
+use common::{boom, get, hub_with_options, hub_with_transport, transactions};
+use sentry_core::{ClientOptions, Hub, Level};
+use sentry_tide::{route, MatchedRoute, PathMatcher, SentryMiddleware};
+
+#[async_std::test]
+async fn transactions_are_named_after_the_route() {
+    let (hub, transport) = hub_with_options(ClientOptions {
+        traces_sample_rate: 1.0,
+        ..Default::default()
+    });
+    let mut app = tide::new();
+    app.with(SentryMiddleware::new().with_hub(hub));
+    route(&mut app, "/users/:id").get(boom);
+    route(&mut app, "/posts/:id").get(|request: tide::Request<()>| async move {
+        Ok(request.ext::<MatchedRoute>().unwrap().as_str().to_string())
+    });
+
+    get(&app, "/users/1").await;
+    let events = transport.fetch_and_clear_events();
+    assert_eq!(events[0].transaction.as_deref(), Some("/users/:id"));
+
+    let mut response = get(&app, "/posts/2").await;
+    assert_eq!(response.body_string().await.unwrap(), "/posts/:id");
+    let transactions = transactions(&transport);
+    assert_eq!(transactions[0].name.as_deref(), Some("/posts/:id"));
+}
+
+#[async_std::test]
+async fn ignored_requests_do_not_name_the_current_hub() {
+    let (hub, _) = hub_with_transport();
+    let (current, transport) = hub_with_transport();
+    Hub::current().bind_client(current.client());
+    let mut app = tide::new();
+    app.with(
+        SentryMiddleware::new()
+            .with_hub(hub)
+            .ignore_paths([PathMatcher::exact("/healthz")]),
+    );
+    route(&mut app, "/healthz").get(|_| async { Ok("ok") });
+
+    get(&app, "/healthz").await;
+    Hub::current().capture_message("unrelated", Level::Info);
+
+    let events = transport.fetch_and_clear_events();
+    assert_eq!(events.len(), 1);
+    assert_eq!(events[0].transaction, None);
+}
@@ -0,0 +1,158 @@
+mod common;
+
+use common::{boom, get, hub_with_transport};
+use sentry_core::protocol::{Event, User};
+use sentry_core::{Breadcrumb, Level};
+use sentry_tide::SentryMiddleware;
+use tide::{Next, Request, StatusCode};
+
+/// The ID of the authenticated user, set in the request extensions by `Authenticate`
+#[derive(Debug, Clone)]
+struct UserId(&'static str);
+
+/// Authenticates every request as the given user, like an authentication middleware outside of
+/// the Sentry middleware
+struct Authenticate(&'static str);
+
+#[async_trait::async_trait]
+impl tide::Middleware<()> for Authenticate {
+    async fn handle(&self, mut request: Request<()>, next: Next<'_, ()>) -> tide::Result {
+        request.set_ext(UserId(self.0));
+        Ok(next.run(request).await)
+    }
+}
+
+/// Reports the given user in the response extensions, like an authentication middleware inside
+/// of the Sentry middleware
+struct ReportUser(&'static str);
+
+#[async_trait::async_trait]
+impl tide::Middleware<()> for ReportUser {
+    async fn handle(&self, request: Request<()>, next: Next<'_, ()>) -> tide::Result {
+        let mut response = next.run(request).await;
+        response.insert_ext(User {
+            id: Some(self.0.into()),
+            ..Default::default()
+        });
+        Ok(response)
+    }
+}
+
+fn user_id(event: &Event<'_>) -> Option<String> {
+    event.user.as_ref()?.id.clone()
+}
+
+fn extract_user(request: &Request<()>) -> Option<User> {
+    request.ext::<UserId>().map(|UserId(id)| User {
+        id: Some(id.to_string()),
+        ..Default::default()
+    })
+}
+
+#[async_std::test]
+async fn user_is_extracted_from_outer_middlewares() {
+    let (hub, transport) = hub_with_transport();
+    let mut app = tide::new();
+    app.with(Authenticate("outer"));
+    app.with(
+        SentryMiddleware::new()
+            .with_hub(hub)
+            .with_user_extractor(extract_user),
+    );
+    app.at("/").get(boom);
+
+    get(&app, "/").await;
+
+    let events = transport.fetch_and_clear_events();
+    assert_eq!(user_id(&events[0]).as_deref(), Some("outer"));
+}
+
+#[async_std::test]
+async fn user_of_the_response_takes_precedence() {
+    let (hub, transport) = hub_with_transport();
+    let mut app = tide::new();
+    app.with(Authenticate("outer"));
+    app.with(
+        SentryMiddleware::new()
+            .with_hub(hub)
+            .with_user_extractor(extract_user),
+    );
+    app.with(ReportUser("inner"));
+    app.at("/").get(boom);
+
+    get(&app, "/").await;
+
+    let events = transport.fetch_and_clear_events();
+    assert_eq!(user_id(&events[0]).as_deref(), Some("inner"));
+}
+
+#[async_std::test]
+async fn scope_is_configured_from_the_request() {
+    let (hub, transport) = hub_with_transport();
+    let mut app = tide::new();
+    app.with(SentryMiddleware::new().with_hub(hub).configure_scope(
+        |request: &Request<()>, scope| {
+            scope.set_tag("path", request.url().path());
+        },
+    ));
+    app.at("/*").get(boom);
+
+    get(&app, "/tagged").await;
+
+    let events = transport.fetch_and_clear_events();
+    assert_eq!(
+        events[0].tags.get("path").map(String::as_str),
+        Some("/tagged")
+    );
+}
+
+#[async_std::test]
+async fn events_carry_the_breadcrumbs_of_the_request_and_response() {
+    let (hub, transport) = hub_with_transport();
+    let mut app = tide::new();
+    app.with(SentryMiddleware::new().with_hub(hub));
+    app.at("/").get(boom);
+
+    get(&app, "/").await;
+
+    let events = transport.fetch_and_clear_events();
+    let breadcrumbs = &events[0].breadcrumbs.values;
+    assert_eq!(breadcrumbs.len(), 2);
+    for breadcrumb in breadcrumbs {
+        assert_eq!(breadcrumb.ty, "http");
+        assert_eq!(breadcrumb.data["method"], "GET");
+        assert_eq!(breadcrumb.data["url"], "http://localhost/");
+    }
+    assert!(!breadcrumbs[0].data.contains_key("status_code"));
+    assert_eq!(breadcrumbs[1].data["status_code"], 500);
+    assert_eq!(breadcrumbs[1].level, Level::Error);
+    assert!(breadcrumbs[1].data.contains_key("duration_ms"));
+}
+
+/// The breadcrumbs left on the parent hub by a request answered with a 404
+async fn parent_breadcrumbs(client_error_breadcrumbs: bool) -> Vec<Breadcrumb> {
+    let (hub, transport) = hub_with_transport();
+    let mut app = tide::new();
+    app.with(
+        SentryMiddleware::new()
+            .with_hub(hub.clone())
+            .client_error_breadcrumbs(client_error_breadcrumbs),
+    );
+    app.at("/").get(|_| async { Ok(StatusCode::NotFound) });
+
+    get(&app, "/").await;
+    hub.capture_message("after", Level::Info);
+
+    let events = transport.fetch_and_clear_events();
+    events[0].breadcrumbs.values.clone()
+}
+
+#[async_std::test]
+async fn client_errors_leave_a_breadcrumb_on_the_parent_hub() {
+    let breadcrumbs = parent_breadcrumbs(true).await;
+    assert_eq!(breadcrumbs.len(), 1);
+    assert_eq!(breadcrumbs[0].data["status_code"], 404);
+    assert_eq!(breadcrumbs[0].level, Level::Warning);
+
+    assert!(parent_breadcrumbs(false).await.is_empty());
+}
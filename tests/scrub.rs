@@ -0,0 +1,141 @@
+mod common;
+
+use common::{boom, hub_with_options, send, url};
+use sentry_core::protocol::Request as SentryRequest;
+use sentry_core::ClientOptions;
+use sentry_tide::SentryMiddleware;
+use tide::http::{Method, Request};
+
+/// The request attached to the event of a failed request with the given URL and headers
+async fn sentry_request(
+    middleware: SentryMiddleware<()>,
+    send_default_pii: bool,
+    path: &str,
+    headers: &[(&str, &str)],
+) -> SentryRequest {
+    let (hub, transport) = hub_with_options(ClientOptions {
+        send_default_pii,
+        ..Default::default()
+    });
+    let mut app = tide::new();
+    app.with(middleware.with_hub(hub));
+    app.at("/").get(boom);
+
+    let mut request = Request::new(Method::Get, url(path));
+    for (name, value) in headers {
+        request.append_header(*name, *value);
+    }
+    send(&app, request).await;
+
+    let events = transport.fetch_and_clear_events();
+    events[0].request.clone().unwrap()
+}
+
+#[async_std::test]
+async fn only_sensitive_query_values_are_replaced() {
+    let request = sentry_request(
+        SentryMiddleware::new(),
+        false,
+        "/?q=a%20b&token=abc&x=1+2",
+        &[],
+    )
+    .await;
+
+    let query = "q=a%20b&token=[Filtered]&x=1+2";
+    assert_eq!(request.query_string.as_deref(), Some(query));
+    assert_eq!(request.url.unwrap().query(), Some(query));
+}
+
+#[async_std::test]
+async fn query_is_untouched_without_sensitive_params() {
+    let request = sentry_request(SentryMiddleware::new(), false, "/?q=a%20b&flag", &[]).await;
+
+    assert_eq!(request.query_string.as_deref(), Some("q=a%20b&flag"));
+}
+
+#[async_std::test]
+async fn sensitive_query_params_replace_the_defaults() {
+    let middleware = SentryMiddleware::new().sensitive_query_params(["Code"]);
+    let path = "/?auth_code=1&token=2&Client%5Fcode=3";
+
+    let request = sentry_request(middleware, false, path, &[]).await;
+
+    assert_eq!(
+        request.query_string.as_deref(),
+        Some("auth_code=[Filtered]&token=2&Client%5Fcode=[Filtered]")
+    );
+}
+
+#[async_std::test]
+async fn cookies_are_attached_with_pii_only() {
+    let headers = [("Cookie", "a=1"), ("Cookie", "b=2")];
+
+    let request = sentry_request(SentryMiddleware::new(), true, "/", &headers).await;
+    assert_eq!(request.cookies.as_deref(), Some("a=1; b=2"));
+
+    let request = sentry_request(SentryMiddleware::new(), false, "/", &headers).await;
+    assert_eq!(request.cookies, None);
+}
+
+#[async_std::test]
+async fn cookies_follow_the_cookie_header() {
+    let headers = [("Cookie", "a=1")];
+
+    let middleware = SentryMiddleware::new().allow_header("cookie");
+    let request = sentry_request(middleware, false, "/", &headers).await;
+    assert_eq!(request.cookies.as_deref(), Some("a=1"));
+
+    let middleware = SentryMiddleware::new().deny_header("cookie");
+    let request = sentry_request(middleware, true, "/", &headers).await;
+    assert_eq!(request.cookies, None);
+}
+
+const CREDENTIALS: [(&str, &str); 4] = [
+    ("Authorization", "Bearer secret"),
+    ("Cookie", "session=secret"),
+    ("X-Api-Key", "secret"),
+    ("Accept", "text/plain"),
+];
+
+#[async_std::test]
+async fn sensitive_headers_are_dropped_without_pii() {
+    let request = sentry_request(SentryMiddleware::new(), false, "/", &CREDENTIALS).await;
+
+    assert_eq!(
+        request.headers.get("accept").map(String::as_str),
+        Some("text/plain")
+    );
+    for name in ["authorization", "cookie", "x-api-key"] {
+        assert!(!request.headers.contains_key(name), "{} is attached", name);
+    }
+
+    let request = sentry_request(SentryMiddleware::new(), true, "/", &CREDENTIALS).await;
+    assert_eq!(request.headers.len(), 4);
+}
+
+#[async_std::test]
+async fn allowed_headers_are_kept_without_pii() {
+    let middleware = SentryMiddleware::new().allow_header("x-api-key");
+
+    let request = sentry_request(middleware, false, "/", &CREDENTIALS).await;
+
+    assert_eq!(
+        request.headers.get("x-api-key").map(String::as_str),
+        Some("secret")
+    );
+    assert!(!request.headers.contains_key("authorization"));
+}
+
+#[async_std::test]
+async fn denied_headers_are_dropped_even_with_pii() {
+    let middleware = SentryMiddleware::new()
+        .allow_header("accept")
+        .deny_header("accept")
+        .deny_header("authorization");
+
+    let request = sentry_request(middleware, true, "/", &CREDENTIALS).await;
+
+    assert!(!request.headers.contains_key("accept"));
+    assert!(!request.headers.contains_key("authorization"));
+    assert!(request.headers.contains_key("x-api-key"));
+}
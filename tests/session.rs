@@ -0,0 +1,99 @@
+mod common;
+
+use std::sync::Arc;
+
+use common::{boom, client_with_options, get};
+use sentry_core::protocol::EnvelopeItem;
+use sentry_core::test::TestTransport;
+use sentry_core::{Client, ClientOptions, Hub, SessionMode};
+use sentry_tide::{SentryMiddleware, SentryRequestExt};
+
+fn client_with_transport(auto_session_tracking: bool) -> (Arc<Client>, Arc<TestTransport>) {
+    client_with_options(ClientOptions {
+        release: Some("app@1.0.0".into()),
+        session_mode: SessionMode::Request,
+        auto_session_tracking,
+        ..Default::default()
+    })
+}
+
+fn app(client: Arc<Client>) -> tide::Server<()> {
+    let hub = Hub::new(Some(client), Default::default());
+    let mut app = tide::new();
+    app.with(
+        SentryMiddleware::new()
+            .with_hub(Arc::new(hub))
+            .capture_panics(true),
+    );
+    app.at("/ok").get(|_| async { Ok("ok") });
+    app.at("/error").get(boom);
+    app.at("/reported")
+        .get(|request: tide::Request<()>| async move {
+            let error = std::io::Error::other("reported");
+            request.capture_sentry_error(&error);
+            Ok("ok")
+        });
+    app.at("/panic").get(|_| async {
+        if true {
+            panic!("boom");
+        }
+        Ok("ok")
+    });
+    app
+}
+
+/// The number of exited, errored and crashed sessions sent by the client
+fn session_counts(client: &Client, transport: &TestTransport) -> (u32, u32, u32) {
+    client.close(None);
+    let mut counts = (0, 0, 0);
+    for envelope in transport.fetch_and_clear_envelopes() {
+        for item in envelope.items() {
+            if let EnvelopeItem::SessionAggregates(aggregates) = item {
+                for bucket in &aggregates.aggregates {
+                    counts.0 += bucket.exited;
+                    counts.1 += bucket.errored;
+                    counts.2 += bucket.crashed;
+                }
+            }
+        }
+    }
+    counts
+}
+
+#[async_std::test]
+async fn only_panics_crash_sessions() {
+    let (client, transport) = client_with_transport(true);
+    let app = app(client.clone());
+
+    get(&app, "/ok").await;
+    get(&app, "/ok").await;
+    get(&app, "/error").await;
+    get(&app, "/reported").await;
+    get(&app, "/panic").await;
+
+    assert_eq!(session_counts(&client, &transport), (2, 2, 1));
+}
+
+#[async_std::test]
+async fn errors_are_reported_as_unhandled() {
+    let (client, transport) = client_with_transport(true);
+    let app = app(client);
+
+    get(&app, "/error").await;
+
+    let events = transport.fetch_and_clear_events();
+    assert_eq!(events.len(), 1);
+    let mechanism = events[0].exception.values[0].mechanism.as_ref().unwrap();
+    assert_eq!(mechanism.handled, Some(false));
+}
+
+#[async_std::test]
+async fn sessions_need_auto_session_tracking() {
+    let (client, transport) = client_with_transport(false);
+    let app = app(client.clone());
+
+    get(&app, "/ok").await;
+    get(&app, "/error").await;
+
+    assert_eq!(session_counts(&client, &transport), (0, 0, 0));
+}
@@ -0,0 +1,123 @@
+#![cfg(feature = "surf")]
+
+mod common;
+
+use std::sync::{Arc, Mutex};
+
+use common::{hub_with_options, send, url};
+use http_client::{Error, HttpClient};
+use sentry_core::{ClientOptions, Hub};
+use sentry_tide::surf::SentrySurfMiddleware;
+use sentry_tide::SentryMiddleware;
+use tide::http::{Method, Request};
+
+const SENTRY_TRACE: &str = "09e04486820349518ac7b5d2adbf6ba5-9cf635fa5b870b3a-1";
+const BAGGAGE: &str = "other=1,sentry-trace_id=09e04486820349518ac7b5d2adbf6ba5,sentry-public_key=upstream,sentry-sampled=true";
+
+/// The `sentry-trace` and `baggage` headers of a request
+type Headers = (Option<String>, Option<String>);
+
+/// An HTTP client which records the propagation headers of the requests
+#[derive(Debug, Clone, Default)]
+struct RecordingClient(Arc<Mutex<Vec<Headers>>>);
+
+#[async_trait::async_trait]
+impl HttpClient for RecordingClient {
+    async fn send(&self, request: http_client::Request) -> Result<http_client::Response, Error> {
+        let header = |name| request.header(name).map(|x| x.as_str().to_string());
+        let headers = (header("sentry-trace"), header("baggage"));
+        self.0.lock().unwrap().push(headers);
+        Ok(http_client::Response::new(200))
+    }
+}
+
+/// Make an outgoing request while handling an incoming one, and return its headers
+async fn outgoing_headers(middleware: SentryMiddleware<()>, incoming: &[(&str, &str)]) -> Headers {
+    let recording = RecordingClient::default();
+    let client =
+        surf::Client::with_http_client(recording.clone()).with(SentrySurfMiddleware::new());
+    let mut app = tide::with_state(());
+    app.with(middleware);
+    app.at("/").get(move |_| {
+        let client = client.clone();
+        async move {
+            client.get("http://upstream/").await?;
+            Ok("ok")
+        }
+    });
+
+    let mut request = Request::new(Method::Get, url("/"));
+    for (name, value) in incoming {
+        request.insert_header(*name, *value);
+    }
+    send(&app, request).await;
+
+    let headers = recording.0.lock().unwrap().pop();
+    headers.unwrap()
+}
+
+fn hub(traces_sample_rate: f32) -> Arc<Hub> {
+    let (hub, _) = hub_with_options(ClientOptions {
+        release: Some("app@1.0.0".into()),
+        traces_sample_rate,
+        ..Default::default()
+    });
+    hub
+}
+
+#[async_std::test]
+async fn incoming_dynamic_sampling_context_is_propagated() {
+    let middleware = SentryMiddleware::new().with_hub(hub(1.0));
+    let incoming = [("sentry-trace", SENTRY_TRACE), ("baggage", BAGGAGE)];
+
+    let (sentry_trace, baggage) = outgoing_headers(middleware, &incoming).await;
+
+    let sentry_trace = sentry_trace.unwrap();
+    assert!(sentry_trace.starts_with("09e04486820349518ac7b5d2adbf6ba5-"));
+    assert!(sentry_trace.ends_with("-1"));
+    assert_eq!(
+        baggage.as_deref(),
+        Some("sentry-trace_id=09e04486820349518ac7b5d2adbf6ba5,sentry-public_key=upstream,sentry-sampled=true")
+    );
+}
+
+#[async_std::test]
+async fn dynamic_sampling_context_is_built_at_the_head_of_the_trace() {
+    let middleware = SentryMiddleware::new().with_hub(hub(1.0));
+
+    let (sentry_trace, baggage) = outgoing_headers(middleware, &[]).await;
+
+    let trace_id = sentry_trace.unwrap().split('-').next().unwrap().to_string();
+    let expected = format!(
+        "sentry-trace_id={},sentry-public_key=public,sentry-release=app%401%2E0%2E0,sentry-sampled=true",
+        trace_id
+    );
+    assert_eq!(baggage, Some(expected));
+}
+
+#[async_std::test]
+async fn incoming_trace_is_propagated_without_a_span() {
+    let middleware = SentryMiddleware::new()
+        .with_hub(hub(1.0))
+        .start_transaction(false);
+    let incoming = [("sentry-trace", SENTRY_TRACE), ("baggage", BAGGAGE)];
+
+    let (sentry_trace, baggage) = outgoing_headers(middleware, &incoming).await;
+
+    assert_eq!(sentry_trace.as_deref(), Some(SENTRY_TRACE));
+    assert_eq!(
+        baggage.as_deref(),
+        Some("sentry-trace_id=09e04486820349518ac7b5d2adbf6ba5,sentry-public_key=upstream,sentry-sampled=true")
+    );
+}
+
+#[async_std::test]
+async fn nothing_is_propagated_without_a_trace() {
+    let middleware = SentryMiddleware::new()
+        .with_hub(hub(1.0))
+        .start_transaction(false);
+
+    let headers = outgoing_headers(middleware, &[]).await;
+
+    assert_eq!(headers, (None, None));
+}
@@ -0,0 +1,59 @@
+mod common;
+
+use std::sync::Arc;
+
+use common::{get, hub_with_options, status, transactions};
+use sentry_core::protocol::SpanStatus;
+use sentry_core::test::TestTransport;
+use sentry_core::{ClientOptions, Hub};
+use sentry_tide::SentryMiddleware;
+use tide::StatusCode;
+
+fn traced_hub() -> (Arc<Hub>, Arc<TestTransport>) {
+    hub_with_options(ClientOptions {
+        traces_sample_rate: 1.0,
+        ..Default::default()
+    })
+}
+
+fn app(hub: Arc<Hub>) -> tide::Server<()> {
+    let mut app = tide::new();
+    app.with(SentryMiddleware::new().with_hub(hub));
+    app.at("/ok").get(|_| async { Ok("ok") });
+    app.at("/missing").get(|_| async {
+        Err::<String, _>(tide::Error::from_str(StatusCode::NotFound, "missing"))
+    });
+    app.at("/error").get(|_| async {
+        Err::<String, _>(tide::Error::from_str(
+            StatusCode::ServiceUnavailable,
+            "down",
+        ))
+    });
+    app
+}
+
+#[async_std::test]
+async fn transactions_are_finished_with_the_response_status() {
+    let (hub, transport) = traced_hub();
+    let app = app(hub);
+
+    get(&app, "/ok").await;
+
+    let transactions = transactions(&transport);
+    assert_eq!(transactions.len(), 1);
+    assert_eq!(status(&transactions[0]), Some(SpanStatus::Ok));
+}
+
+#[async_std::test]
+async fn transactions_of_errors_have_their_status() {
+    let (hub, transport) = traced_hub();
+    let app = app(hub);
+
+    get(&app, "/missing").await;
+    get(&app, "/error").await;
+
+    let transactions = transactions(&transport);
+    assert_eq!(transactions.len(), 2);
+    assert_eq!(status(&transactions[0]), Some(SpanStatus::NotFound));
+    assert_eq!(status(&transactions[1]), Some(SpanStatus::Unavailable));
+}
@@ -0,0 +1,195 @@
+#![cfg(feature = "async-std-transport")]
+
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use async_std::io::prelude::*;
+use async_std::io::BufReader;
+use async_std::net::{TcpListener, TcpStream};
+use async_std::task;
+use sentry_core::protocol::{Envelope, EnvelopeItem, Event, Transaction};
+use sentry_core::{ClientOptions, Transport};
+use sentry_tide::transport::AsyncStdTransport;
+
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+/// A Sentry server which records the received envelopes, and answers with the queued responses
+/// then with plain 200s
+#[derive(Clone, Default)]
+struct MockServer {
+    envelopes: Arc<Mutex<Vec<String>>>,
+    responses: Arc<Mutex<Vec<&'static str>>>,
+}
+
+impl MockServer {
+    /// Starts the server, and returns the DSN which points to it
+    async fn start(&self) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let server = self.clone();
+        task::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                task::spawn(server.clone().serve(stream));
+            }
+        });
+        format!("http://public@127.0.0.1:{}/1", port)
+    }
+
+    /// Queue the status line and headers of a response
+    fn respond(&self, head: &'static str) {
+        self.responses.lock().unwrap().push(head);
+    }
+
+    fn envelopes(&self) -> Vec<String> {
+        self.envelopes.lock().unwrap().clone()
+    }
+
+    async fn serve(self, mut stream: TcpStream) {
+        let mut reader = BufReader::new(stream.clone());
+        loop {
+            let mut content_length = None;
+            let mut line = String::new();
+            loop {
+                line.clear();
+                if reader.read_line(&mut line).await.unwrap_or(0) == 0 {
+                    return;
+                }
+                let header = line.trim_end();
+                if header.is_empty() {
+                    break;
+                }
+                if let Some((name, value)) = header.split_once(':') {
+                    if name.eq_ignore_ascii_case("content-length") {
+                        content_length = value.trim().parse().ok();
+                    }
+                }
+            }
+
+            let mut body = vec![0; content_length.unwrap_or(0)];
+            reader.read_exact(&mut body).await.unwrap();
+            self.envelopes
+                .lock()
+                .unwrap()
+                .push(String::from_utf8(body).unwrap());
+
+            let head = {
+                let mut responses = self.responses.lock().unwrap();
+                if responses.is_empty() {
+                    "HTTP/1.1 200 OK"
+                } else {
+                    responses.remove(0)
+                }
+            };
+            let response = format!("{}\r\ncontent-length: 0\r\n\r\n", head);
+            if stream.write_all(response.as_bytes()).await.is_err() {
+                return;
+            }
+        }
+    }
+}
+
+fn transport(dsn: &str) -> AsyncStdTransport {
+    let options = ClientOptions {
+        dsn: Some(dsn.parse().unwrap()),
+        ..Default::default()
+    };
+    AsyncStdTransport::new(&options)
+}
+
+fn event_envelope() -> Envelope {
+    Envelope::from(Event::default())
+}
+
+fn transaction_envelope() -> Envelope {
+    let mut envelope = Envelope::new();
+    envelope.add_item(EnvelopeItem::Transaction(Transaction::default()));
+    envelope
+}
+
+/// Whether the envelope has an item of the given type
+fn has_item(envelope: &str, ty: &str) -> bool {
+    envelope.contains(&format!("\"type\":\"{}\"", ty))
+}
+
+#[async_std::test]
+async fn envelopes_are_delivered() {
+    let server = MockServer::default();
+    let transport = transport(&server.start().await);
+
+    let event = Event::default();
+    let event_id = event.event_id;
+    transport.send_envelope(Envelope::from(event));
+    assert!(transport.flush_async(TIMEOUT).await);
+
+    let envelopes = server.envelopes();
+    assert_eq!(envelopes.len(), 1);
+    assert!(envelopes[0].contains(&event_id.as_simple().to_string()));
+}
+
+#[async_std::test]
+async fn rate_limited_categories_are_dropped() {
+    let server = MockServer::default();
+    server.respond("HTTP/1.1 200 OK\r\nx-sentry-rate-limits: 60:transaction:organization");
+    let transport = transport(&server.start().await);
+
+    transport.send_envelope(event_envelope());
+    assert!(transport.flush_async(TIMEOUT).await);
+
+    // Only the transactions are rate limited
+    let mut envelope = event_envelope();
+    envelope.add_item(EnvelopeItem::Transaction(Transaction::default()));
+    transport.send_envelope(envelope);
+    transport.send_envelope(transaction_envelope());
+    assert!(transport.flush_async(TIMEOUT).await);
+
+    let envelopes = server.envelopes();
+    assert_eq!(envelopes.len(), 2);
+    assert!(has_item(&envelopes[1], "event"));
+    assert!(!has_item(&envelopes[1], "transaction"));
+}
+
+#[async_std::test]
+async fn retry_after_applies_to_429_only() {
+    let server = MockServer::default();
+    server.respond("HTTP/1.1 503 Service Unavailable\r\nretry-after: 60");
+    let transport = transport(&server.start().await);
+
+    transport.send_envelope(event_envelope());
+    transport.send_envelope(event_envelope());
+    assert!(transport.flush_async(TIMEOUT).await);
+    assert_eq!(server.envelopes().len(), 2);
+
+    server.respond("HTTP/1.1 429 Too Many Requests\r\nretry-after: 60");
+    transport.send_envelope(event_envelope());
+    transport.send_envelope(event_envelope());
+    assert!(transport.flush_async(TIMEOUT).await);
+    assert_eq!(server.envelopes().len(), 3);
+}
+
+#[async_std::test]
+async fn unusable_retry_after_falls_back_to_the_default() {
+    let server = MockServer::default();
+    server.respond("HTTP/1.1 200 OK\r\nx-sentry-rate-limits: inf:error, 1e20:session");
+    server.respond("HTTP/1.1 429 Too Many Requests\r\nretry-after: inf");
+    let transport = transport(&server.start().await);
+
+    transport.send_envelope(transaction_envelope());
+    assert!(transport.flush_async(TIMEOUT).await);
+    transport.send_envelope(event_envelope());
+    transport.send_envelope(transaction_envelope());
+    assert!(transport.flush_async(TIMEOUT).await);
+    assert_eq!(server.envelopes().len(), 2);
+    assert!(has_item(&server.envelopes()[1], "transaction"));
+
+    // The worker backs off after the 429, and is still alive
+    transport.send_envelope(transaction_envelope());
+    assert!(transport.flush_async(TIMEOUT).await);
+    assert_eq!(server.envelopes().len(), 2);
+}
+
+#[async_std::test]
+async fn flush_resolves_without_a_dsn() {
+    let transport = AsyncStdTransport::new(&ClientOptions::default());
+    transport.send_envelope(event_envelope());
+    assert!(transport.flush_async(TIMEOUT).await);
+}